@@ -0,0 +1,84 @@
+// Задержка списка и статистики журнала не должна расти вместе с числом записей.
+// Запуск: cargo bench --bench logs_store
+use std::{hint::black_box, path::PathBuf, sync::Arc};
+use chrono::{DateTime, Duration, Utc};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tokio::runtime::{Builder, Runtime};
+use untitled::{
+    logs::{LogCategory, LogEntry, LogFilter, LogLevel, LogsStore, Matcher},
+    settings::LogsConfig,
+    storage::{Db, MemoryStorage},
+};
+
+const SIZES: &[usize] = &[10_000, 100_000, 1_000_000];
+const MAX: usize = 1_000_000;
+const BATCH: usize = 10_000;
+const PAGE: usize = 100;
+// Записи идут с шагом в 1 мс; диапазон времени — 5 секунд посередине журнала
+const RANGE_MS: i64 = 5_000;
+
+fn entry(base: DateTime<Utc>, i: usize) -> LogEntry {
+    // Примерно одна ошибка на сотню записей, категории по кругу
+    let level = match i % 100 { 0 => LogLevel::Error, 1..=4 => LogLevel::Warning, 5..=20 => LogLevel::Debug, _ => LogLevel::Info };
+    let category = [LogCategory::System, LogCategory::Database, LogCategory::Security, LogCategory::Api, LogCategory::User, LogCategory::Network][i % 6];
+    let mut log = LogEntry::new(level, category, format!("request {} handled", i), "bench.rs:1");
+    log.timestamp = base + Duration::milliseconds(i as i64);
+    log
+}
+
+// Сегменты — во временном каталоге; None — журнал только в памяти
+fn store(rt: &Runtime, dir: Option<&PathBuf>, base: DateTime<Utc>, size: usize) -> LogsStore {
+    if let Some(dir) = dir { let _ = std::fs::remove_dir_all(dir); }
+    let db: Db = Arc::new(MemoryStorage::default());
+    let store = LogsStore::open_in(&db, dir.cloned());
+    rt.block_on(async {
+        let policy = LogsConfig { memory_entries: MAX, max_age_days: 0, ..LogsConfig::default() };
+        store.apply_retention(&policy).await;
+        for start in (0..size).step_by(BATCH) {
+            store.append_many((start..(start + BATCH).min(size)).map(|i| entry(base, i)).collect()).await;
+        }
+        // Дожидается потока записи: политика применяется после всех записей в очереди
+        store.apply_retention(&policy).await;
+    });
+    store
+}
+
+fn filter(filter: LogFilter) -> Matcher {
+    filter.compile().unwrap()
+}
+
+fn bench_store(c: &mut Criterion, name: &str, dir: Option<PathBuf>) {
+    let rt = Builder::new_current_thread().enable_all().build().unwrap();
+    let base = Utc::now() - Duration::hours(1);
+    let all = filter(LogFilter::default());
+    let errors = filter(LogFilter { level: vec![LogLevel::Error], ..LogFilter::default() });
+    let mut group = c.benchmark_group(name);
+    group.sample_size(20);
+    for &size in SIZES {
+        let store = store(&rt, dir.as_ref(), base, size);
+        let from = base + Duration::milliseconds(size as i64 / 2);
+        let range = filter(LogFilter { from: Some(from), to: Some(from + Duration::milliseconds(RANGE_MS)), ..LogFilter::default() });
+        group.bench_with_input(BenchmarkId::new("list", size), &size, |b, _| b.iter(|| rt.block_on(store.query(black_box(&all), None, PAGE))));
+        group.bench_with_input(BenchmarkId::new("list_errors", size), &size, |b, _| b.iter(|| rt.block_on(store.query(black_box(&errors), None, PAGE))));
+        group.bench_with_input(BenchmarkId::new("list_range", size), &size, |b, _| b.iter(|| rt.block_on(store.query(black_box(&range), None, PAGE))));
+        group.bench_with_input(BenchmarkId::new("stats", size), &size, |b, _| b.iter(|| rt.block_on(store.stats(black_box(&all)))));
+        group.bench_with_input(BenchmarkId::new("stats_errors", size), &size, |b, _| b.iter(|| rt.block_on(store.stats(black_box(&errors)))));
+        group.bench_with_input(BenchmarkId::new("stats_range", size), &size, |b, _| b.iter(|| rt.block_on(store.stats(black_box(&range)))));
+        group.bench_with_input(BenchmarkId::new("append", size), &size, |b, _| {
+            b.iter(|| rt.block_on(store.append_many((0..PAGE).map(|i| entry(base, size + i)).collect())))
+        });
+    }
+    group.finish();
+    if let Some(dir) = dir { let _ = std::fs::remove_dir_all(dir); }
+}
+
+fn memory(c: &mut Criterion) {
+    bench_store(c, "logs_memory", None);
+}
+
+fn segments(c: &mut Criterion) {
+    bench_store(c, "logs_segments", Some(std::env::temp_dir().join(format!("dc_bench_logs_{}", std::process::id()))));
+}
+
+criterion_group!(benches, memory, segments);
+criterion_main!(benches);
@@ -0,0 +1,349 @@
+use std::{collections::{HashMap, VecDeque}, sync::Arc};
+use axum::{extract::{Path, Query, State}, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast::error::RecvError, watch, RwLock};
+use uuid::Uuid;
+use crate::{
+    auth::CurrentUser,
+    logs::{LogCategory, LogEntry, LogFilter, LogLevel, LogsStore, Matcher, SavedSearch, SavedSearches},
+    settings::SettingsStore,
+    storage::{Collection, Db},
+};
+
+// Источник записей, которые пишут сами оповещения; правила их не видят, иначе
+// правило на предупреждения срабатывало бы от собственных оповещений
+const ALERT_SOURCE: &str = "alerts";
+const MAX_HISTORY: usize = 1000;
+
+fn default_enabled() -> bool { true }
+fn default_threshold() -> usize { 1 }
+fn default_window() -> u64 { 300 }
+fn default_cooldown() -> u64 { 600 }
+
+// Правило срабатывает, когда за window_secs пришло не меньше threshold записей,
+// подходящих под фильтр и (если задан) регулярное выражение по message и details.
+// С search_id фильтр берётся из сохранённого поиска (без диапазона времени) и следует за его правками.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub id: Uuid,
+    pub name: String,
+    pub enabled: bool,
+    pub filter: LogFilter,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search_id: Option<Uuid>,
+    pub pattern: Option<String>,
+    pub threshold: usize,
+    pub window_secs: u64,
+    // Повторные срабатывания в течение этого времени подавляются
+    pub cooldown_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpsertRule {
+    pub name: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub filter: LogFilter,
+    #[serde(default)]
+    pub search_id: Option<Uuid>,
+    #[serde(default)]
+    pub pattern: Option<String>,
+    #[serde(default = "default_threshold")]
+    pub threshold: usize,
+    #[serde(default = "default_window")]
+    pub window_secs: u64,
+    #[serde(default = "default_cooldown")]
+    pub cooldown_secs: u64,
+}
+
+impl UpsertRule {
+    // searches — поиски, доступные автору правила
+    fn into_rule(self, id: Uuid, searches: &[SavedSearch]) -> Result<AlertRule, String> {
+        let name = self.name.trim();
+        if name.is_empty() { return Err("name must not be empty".into()); }
+        if self.threshold == 0 { return Err("threshold must be positive".into()); }
+        if self.window_secs == 0 { return Err("window_secs must be positive".into()); }
+        let pattern = self.pattern.filter(|p| !p.is_empty());
+        let rule = AlertRule { id, name: name.to_string(), enabled: self.enabled, filter: self.filter, search_id: self.search_id, pattern, threshold: self.threshold, window_secs: self.window_secs, cooldown_secs: self.cooldown_secs };
+        Compiled::new(&rule, searches)?;
+        Ok(rule)
+    }
+}
+
+// Запись истории оповещений
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertEvent {
+    pub id: Uuid,
+    pub rule_id: Uuid,
+    pub rule_name: String,
+    pub fired_at: DateTime<Utc>,
+    // Сколько подходящих записей было в окне (не больше порога)
+    pub count: usize,
+    // Переключатель уведомлений, который отвечает за это оповещение
+    pub channel: String,
+    // false — оповещение записано, но уведомление выключено в настройках
+    pub notified: bool,
+    pub trigger: LogEntry,
+}
+
+struct Compiled { rule: AlertRule, matcher: Matcher, pattern: Option<Regex> }
+
+impl Compiled {
+    fn new(rule: &AlertRule, searches: &[SavedSearch]) -> Result<Self, String> {
+        let filter = match rule.search_id {
+            Some(id) => {
+                let search = searches.iter().find(|s| s.id == id).ok_or_else(|| format!("saved search {} not found", id))?;
+                // Правило смотрит на поступающие записи, диапазон времени поиска к нему не относится
+                LogFilter { from: None, to: None, ..search.filter.clone() }
+            }
+            None => rule.filter.clone(),
+        };
+        let matcher = filter.compile()?;
+        let pattern = rule.pattern.as_deref().map(Regex::new).transpose().map_err(|e| format!("invalid pattern: {}", e))?;
+        Ok(Self { rule: rule.clone(), matcher, pattern })
+    }
+
+    fn matches(&self, log: &LogEntry) -> bool {
+        if !self.matcher.matches(log) { return false; }
+        match &self.pattern {
+            Some(re) => re.is_match(&log.message) || log.details.as_deref().is_some_and(|d| re.is_match(d)),
+            None => true,
+        }
+    }
+}
+
+// Скользящее окно попаданий и время последнего срабатывания
+#[derive(Default)]
+struct RuleState { hits: VecDeque<DateTime<Utc>>, last_fired: Option<DateTime<Utc>> }
+
+#[derive(Debug, Clone)]
+pub struct AlertsStore {
+    rules: Arc<RwLock<Vec<AlertRule>>>,
+    history: Arc<RwLock<Vec<AlertEvent>>>,
+    rules_db: Collection,
+    history_db: Collection,
+    changes: Arc<watch::Sender<Vec<AlertRule>>>,
+    searches: SavedSearches,
+}
+
+impl AlertsStore {
+    pub fn open(db: &Db, searches: SavedSearches) -> Self {
+        let rules_db = Collection::new(db, "alert_rules");
+        let history_db = Collection::new(db, "alert_history").newest_first();
+        let rules = rules_db.load_or_seed(|r: &AlertRule| r.id.to_string(), Vec::new);
+        let history = history_db.load_or_seed(|e: &AlertEvent| e.id.to_string(), Vec::new);
+        searches.set_rules(search_rules(&rules));
+        Self {
+            changes: Arc::new(watch::Sender::new(rules.clone())),
+            rules: Arc::new(RwLock::new(rules)),
+            history: Arc::new(RwLock::new(history)),
+            rules_db,
+            history_db,
+            searches,
+        }
+    }
+
+    async fn publish(&self) {
+        let rules = self.rules.read().await.clone();
+        self.searches.set_rules(search_rules(&rules));
+        self.changes.send_replace(rules);
+    }
+
+    async fn record(&self, event: AlertEvent) {
+        let mut history = self.history.write().await;
+        history.insert(0, event.clone());
+        self.history_db.put(&event.id.to_string(), &event);
+        if history.len() > MAX_HISTORY {
+            for old in history.split_off(MAX_HISTORY) { self.history_db.remove(&old.id.to_string()); }
+        }
+    }
+
+    // Фоновая проверка правил по каждой новой записи журнала
+    pub fn spawn_evaluator(&self, logs: LogsStore, settings: SettingsStore) {
+        let store = self.clone();
+        let mut rules_rx = self.changes.subscribe();
+        let mut searches_rx = self.searches.subscribe();
+        let mut logs_rx = logs.subscribe();
+        tokio::spawn(async move {
+            let mut compiled = compile_all(&rules_rx.borrow_and_update(), &searches_rx.borrow_and_update());
+            let mut state: HashMap<Uuid, RuleState> = HashMap::new();
+            loop {
+                tokio::select! {
+                    changed = rules_rx.changed() => {
+                        if changed.is_err() { break; }
+                        compiled = compile_all(&rules_rx.borrow_and_update(), &searches_rx.borrow());
+                        state.retain(|id, _| compiled.iter().any(|c| c.rule.id == *id));
+                    }
+                    changed = searches_rx.changed() => {
+                        if changed.is_err() { break; }
+                        compiled = compile_all(&rules_rx.borrow(), &searches_rx.borrow_and_update());
+                        state.retain(|id, _| compiled.iter().any(|c| c.rule.id == *id));
+                    }
+                    log = logs_rx.recv() => {
+                        let log = match log {
+                            Ok(log) => log,
+                            Err(RecvError::Lagged(skipped)) => { tracing::warn!(skipped, "Alert evaluator lagged behind the log stream"); continue; }
+                            Err(RecvError::Closed) => break,
+                        };
+                        if log.source == ALERT_SOURCE { continue; }
+                        let now = Utc::now();
+                        for rule in compiled.iter().filter(|c| c.rule.enabled && c.matches(&log)) {
+                            let st = state.entry(rule.rule.id).or_default();
+                            // Окно считается по времени поступления: записи могут приходить с прошлым временем события
+                            let window_start = now - Duration::seconds(rule.rule.window_secs as i64);
+                            st.hits.push_back(now);
+                            while st.hits.front().is_some_and(|t| *t < window_start) || st.hits.len() > rule.rule.threshold { st.hits.pop_front(); }
+                            if st.hits.len() < rule.rule.threshold { continue; }
+                            if st.last_fired.is_some_and(|t| now - t < Duration::seconds(rule.rule.cooldown_secs as i64)) { continue; }
+                            st.last_fired = Some(now);
+                            let count = st.hits.len();
+                            st.hits.clear();
+                            store.fire(&rule.rule, count, &log, &logs, &settings).await;
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    async fn fire(&self, rule: &AlertRule, count: usize, trigger: &LogEntry, logs: &LogsStore, settings: &SettingsStore) {
+        let notifications = settings.inner.read().await.notifications.clone();
+        let (channel, notified) = match (trigger.category, trigger.level) {
+            (LogCategory::Security, _) => ("security_events", notifications.security_events),
+            (_, LogLevel::Error) => ("error_reports", notifications.error_reports),
+            _ => ("system_alerts", notifications.system_alerts),
+        };
+        let event = AlertEvent {
+            id: Uuid::new_v4(),
+            rule_id: rule.id,
+            rule_name: rule.name.clone(),
+            fired_at: Utc::now(),
+            count,
+            channel: channel.into(),
+            notified,
+            trigger: trigger.clone(),
+        };
+        self.record(event).await;
+        if !notified { return; }
+        let category = if trigger.category == LogCategory::Security { LogCategory::Security } else { LogCategory::System };
+        let mut entry = LogEntry::new(LogLevel::Warning, category, format!("Сработало правило «{}»", rule.name), ALERT_SOURCE);
+        entry.details = Some(format!("{} записей за {} с; последняя: {}", count, rule.window_secs, trigger.message));
+        logs.append(entry).await;
+    }
+}
+
+fn search_rules(rules: &[AlertRule]) -> HashMap<Uuid, Vec<String>> {
+    let mut by_search: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for rule in rules {
+        if let Some(id) = rule.search_id { by_search.entry(id).or_default().push(rule.name.clone()); }
+    }
+    by_search
+}
+
+fn compile_all(rules: &[AlertRule], searches: &[SavedSearch]) -> Vec<Compiled> {
+    rules.iter().filter_map(|r| match Compiled::new(r, searches) {
+        Ok(c) => Some(c),
+        Err(e) => { tracing::error!(rule = %r.id, error = %e, "Skipping invalid alert rule"); None }
+    }).collect()
+}
+
+pub async fn list_rules(State(store): State<AlertsStore>) -> Json<Vec<AlertRule>> {
+    Json(store.rules.read().await.clone())
+}
+
+pub async fn create_rule(State(store): State<AlertsStore>, CurrentUser(user): CurrentUser, Json(payload): Json<UpsertRule>) -> impl IntoResponse {
+    let rule = match payload.into_rule(Uuid::new_v4(), &store.searches.visible_to(&user).await) {
+        Ok(rule) => rule,
+        Err(e) => return (StatusCode::UNPROCESSABLE_ENTITY, e).into_response(),
+    };
+    store.rules.write().await.push(rule.clone());
+    store.rules_db.put(&rule.id.to_string(), &rule);
+    store.publish().await;
+    (StatusCode::CREATED, Json(rule)).into_response()
+}
+
+pub async fn update_rule(State(store): State<AlertsStore>, CurrentUser(user): CurrentUser, Path(id): Path<Uuid>, Json(payload): Json<UpsertRule>) -> impl IntoResponse {
+    let rule = match payload.into_rule(id, &store.searches.visible_to(&user).await) {
+        Ok(rule) => rule,
+        Err(e) => return (StatusCode::UNPROCESSABLE_ENTITY, e).into_response(),
+    };
+    {
+        let mut rules = store.rules.write().await;
+        let Some(item) = rules.iter_mut().find(|r| r.id == id) else { return (StatusCode::NOT_FOUND, "Not found").into_response() };
+        *item = rule.clone();
+        store.rules_db.put(&id.to_string(), &rule);
+    }
+    store.publish().await;
+    Json(rule).into_response()
+}
+
+pub async fn delete_rule(State(store): State<AlertsStore>, Path(id): Path<Uuid>) -> impl IntoResponse {
+    {
+        let mut rules = store.rules.write().await;
+        let before = rules.len();
+        rules.retain(|r| r.id != id);
+        if rules.len() == before { return (StatusCode::NOT_FOUND, "Not found").into_response(); }
+        store.rules_db.remove(&id.to_string());
+    }
+    store.publish().await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryParams {
+    #[serde(default)]
+    pub rule_id: Option<Uuid>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+pub async fn alert_history(State(store): State<AlertsStore>, Query(params): Query<HistoryParams>) -> Json<Vec<AlertEvent>> {
+    let history = store.history.read().await;
+    let limit = params.limit.unwrap_or(100).min(MAX_HISTORY);
+    Json(history.iter().filter(|e| params.rule_id.is_none_or(|id| e.rule_id == id)).take(limit).cloned().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration as StdDuration;
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[tokio::test]
+    async fn deduped_burst_trips_threshold_rule() {
+        let db: Db = Arc::new(MemoryStorage::default());
+        let settings = SettingsStore::open(&db);
+        settings.update(|cfg| cfg.logs.dedup_window_secs = 300).await;
+        let logs = LogsStore::open_in(&db, None);
+        logs.spawn_dedup(settings.clone());
+        let alerts = AlertsStore::open(&db, logs.searches().clone());
+        let filter = LogFilter { level: vec![LogLevel::Error], category: vec![LogCategory::Database], ..LogFilter::default() };
+        let rule = AlertRule { id: Uuid::new_v4(), name: "db flapping".into(), enabled: true, filter, search_id: None, pattern: None, threshold: 5, window_secs: 300, cooldown_secs: 0 };
+        alerts.rules.write().await.push(rule.clone());
+        alerts.publish().await;
+        alerts.spawn_evaluator(logs.clone(), settings.clone());
+        // Задача дедупликации применяет настройки на первом тике
+        tokio::time::sleep(StdDuration::from_millis(50)).await;
+
+        for _ in 0..5 {
+            logs.append(LogEntry::new(LogLevel::Error, LogCategory::Database, "connection refused", "db.rs:1")).await;
+        }
+        let matcher = LogFilter { category: vec![LogCategory::Database], ..LogFilter::default() }.compile().unwrap();
+        let (stored, _) = logs.query(&matcher, None, 10).await;
+        let collapsed: Vec<_> = stored.iter().filter(|l| l.message == "connection refused").collect();
+        assert_eq!(collapsed.len(), 1);
+        assert_eq!(collapsed[0].occurrences(), 5);
+
+        let fired = tokio::time::timeout(StdDuration::from_secs(2), async {
+            loop {
+                if let Some(event) = alerts.history.read().await.first().cloned() { return event; }
+                tokio::time::sleep(StdDuration::from_millis(10)).await;
+            }
+        }).await.expect("rule did not fire");
+        assert_eq!(fired.rule_id, rule.id);
+        assert_eq!(fired.count, 5);
+    }
+}
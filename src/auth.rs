@@ -0,0 +1,433 @@
+use std::{collections::{HashMap, HashSet}, net::IpAddr, sync::{Arc, LazyLock}};
+use argon2::{password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString}, Argon2};
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, MatchedPath, Request, State},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+use crate::{
+    ip_filter::ClientIp,
+    lockout::{LoginGuard, LOCKOUT},
+    logs::{accepts_ingest_token, IngestClient, LogCategory, LogEntry, LogLevel, LogsStore, TOKEN_PREFIX},
+    settings::SettingsStore,
+    storage::{Collection, Db},
+    totp,
+    users::{User, UserRole, UserStatus, UsersStore},
+};
+
+const SESSION_COOKIE: &str = "dc_session";
+
+// Маршруты, доступные до подключения 2FA, когда включён Security.require_two_factor
+const ENROLLMENT_ROUTES: &[&str] = &["/api/auth/me", "/api/auth/logout", "/api/auth/2fa/enroll", "/api/auth/2fa/confirm"];
+
+// Cookie сессии помечается Secure и уходит только по HTTPS. Для разработки
+// по plain HTTP это отключается переменной DC_INSECURE_COOKIES=1.
+pub fn secure_cookies_from_env() -> bool {
+    !matches!(std::env::var("DC_INSECURE_COOKIES").as_deref(), Ok("1" | "true"))
+}
+
+fn session_cookie(value: &str, max_age: i64, secure: bool) -> String {
+    let secure = if secure { "; Secure" } else { "" };
+    format!("{}={}; HttpOnly{}; SameSite=Strict; Path=/; Max-Age={}", SESSION_COOKIE, value, secure, max_age)
+}
+
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default().hash_password(password.as_bytes(), &salt).expect("argon2 hashing").to_string()
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+}
+
+// Пароль неизвестного пользователя проверяется против этого хеша, чтобы ответ
+// занимал столько же времени, сколько для существующего имени
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| hash_password(&random_token(16)));
+
+pub fn random_token(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut buf);
+    hex::encode(buf)
+}
+
+// В хранилище лежит только sha256 от токена — утечка базы не даёт готовых сессий
+pub(crate) fn token_id(token: &str) -> String { hex::encode(Sha256::digest(token.as_bytes())) }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub user_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    // Последний запрос с этой сессией — только в памяти, чтобы не писать в базу на каждый запрос
+    #[serde(skip)]
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionStore { inner: Arc<RwLock<HashMap<String, Session>>>, db: Collection }
+
+impl SessionStore {
+    pub fn open(db: &Db) -> Self {
+        let db = Collection::new(db, "sessions");
+        let now = Utc::now();
+        let sessions = db.load_or_seed(|s: &Session| s.id.clone(), Vec::new)
+            .into_iter()
+            .filter(|s| s.expires_at > now)
+            .map(|s| (s.id.clone(), s))
+            .collect();
+        Self { inner: Arc::new(RwLock::new(sessions)), db }
+    }
+
+    // Возвращает токен для клиента; сам токен нигде не сохраняется
+    pub async fn create(&self, user_id: Uuid, ttl: Duration) -> (String, Session) {
+        let token = random_token(32);
+        let now = Utc::now();
+        let session = Session { id: token_id(&token), user_id, created_at: now, expires_at: now + ttl, last_seen: Some(now) };
+        self.db.put(&session.id, &session);
+        self.inner.write().await.insert(session.id.clone(), session.clone());
+        (token, session)
+    }
+
+    pub async fn lookup(&self, token: &str) -> Option<Session> {
+        let id = token_id(token);
+        let now = Utc::now();
+        let mut sessions = self.inner.write().await;
+        let found = sessions.get_mut(&id)?;
+        if found.expires_at > now {
+            found.last_seen = Some(now);
+            return Some(found.clone());
+        }
+        sessions.remove(&id);
+        self.db.remove(&id);
+        None
+    }
+
+    // Число разных пользователей, чьи сессии использовались за последнее `window`
+    pub async fn active_users(&self, window: Duration) -> usize {
+        let since = Utc::now() - window;
+        let sessions = self.inner.read().await;
+        let users: HashSet<Uuid> = sessions.values()
+            .filter(|s| s.last_seen.is_some_and(|seen| seen >= since))
+            .map(|s| s.user_id)
+            .collect();
+        users.len()
+    }
+
+    pub async fn revoke(&self, token: &str) {
+        let id = token_id(token);
+        if self.inner.write().await.remove(&id).is_some() { self.db.remove(&id); }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthState {
+    pub users: UsersStore,
+    pub sessions: SessionStore,
+    pub settings: SettingsStore,
+    pub logs: LogsStore,
+    pub guard: LoginGuard,
+    pub secure_cookies: bool,
+}
+
+impl AuthState {
+    async fn security_log(&self, level: LogLevel, message: &str, details: String, ip: IpAddr, username: &str) {
+        let mut entry = LogEntry::new(level, LogCategory::Security, message, "auth.rs");
+        entry.details = Some(details);
+        entry.ip = Some(ip.to_string());
+        entry.user = Some(username.to_string());
+        self.logs.append(entry).await;
+    }
+
+    // Учитывает неудачную попытку по IP и по учётной записи, блокирует, когда попыток
+    // стало больше Security.max_login_attempts, и пишет всё в журнал безопасности
+    async fn login_failed(&self, ip: IpAddr, user: Option<&User>, username: &str, reason: &str) {
+        let max_attempts = self.settings.inner.read().await.security.max_login_attempts;
+        self.security_log(LogLevel::Warning, "Неудачная попытка входа", format!("{} for user: {}", reason, username), ip, username).await;
+
+        if let Some(until) = self.guard.ip_failed(ip, username, max_attempts).await {
+            let details = format!("Too many failed login attempts from {}, blocked until {}", ip, until.to_rfc3339());
+            self.security_log(LogLevel::Error, "IP-адрес заблокирован", details, ip, username).await;
+        }
+        let Some(user) = user else { return };
+        if max_attempts == 0 { return; }
+        let locked = self.users.update(user.id, |u| {
+            u.credentials.failed_logins += 1;
+            if u.credentials.failed_logins <= max_attempts as u32 { return None; }
+            u.credentials.failed_logins = 0;
+            u.credentials.locked_until = Some(Utc::now() + LOCKOUT);
+            u.credentials.locked_until
+        }).await.flatten();
+        if let Some(until) = locked {
+            let details = format!("{} failed login attempts, locked until {}", max_attempts as u32 + 1, until.to_rfc3339());
+            self.security_log(LogLevel::Error, "Учётная запись заблокирована", details, ip, username).await;
+        }
+    }
+
+    async fn resolve(&self, token: &str) -> Option<User> {
+        let session = self.sessions.lookup(token).await?;
+        self.users.get(session.user_id).await.filter(|u| u.status == UserStatus::Active)
+    }
+}
+
+// Токен берётся из "Authorization: Bearer ..." либо из cookie (нужно для EventSource)
+fn request_token(headers: &HeaderMap) -> Option<String> {
+    if let Some(bearer) = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()).and_then(|v| v.strip_prefix("Bearer ")) {
+        return Some(bearer.trim().to_string());
+    }
+    headers.get_all(header::COOKIE).iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|pair| pair.trim().strip_prefix(SESSION_COOKIE)?.strip_prefix('=').map(str::to_string))
+}
+
+// Текущий пользователь, положенный в запрос middleware `require_auth`
+#[derive(Debug, Clone)]
+pub struct CurrentUser(pub User);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for CurrentUser {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<CurrentUser>().cloned().ok_or((StatusCode::UNAUTHORIZED, "Authentication required"))
+    }
+}
+
+pub async fn require_auth(State(state): State<AuthState>, mut req: Request, next: Next) -> Response {
+    let token = request_token(req.headers());
+    // Токен приёма журнала не даёт пользователя и годится только для маршрутов приёма
+    if let Some(token) = token.as_deref().filter(|t| t.starts_with(TOKEN_PREFIX)) {
+        let Some(client) = state.logs.tokens().lookup(token).await else {
+            return (StatusCode::UNAUTHORIZED, "Invalid ingest token").into_response();
+        };
+        let route = req.extensions().get::<MatchedPath>().map(|p| p.as_str()).unwrap_or_default();
+        if !accepts_ingest_token(req.method(), route.strip_prefix("/api").unwrap_or(route)) {
+            return (StatusCode::FORBIDDEN, "Ingest token is only valid for POST /api/logs and /api/logs/batch").into_response();
+        }
+        req.extensions_mut().insert(IngestClient(client));
+        return next.run(req).await;
+    }
+    let user = match token {
+        Some(token) => state.resolve(&token).await,
+        None => None,
+    };
+    let (require_auth, require_two_factor) = {
+        let cfg = state.settings.inner.read().await;
+        (cfg.api.require_auth, cfg.security.require_two_factor)
+    };
+    match user {
+        Some(user) => {
+            let route = req.extensions().get::<MatchedPath>().map(|p| p.as_str()).unwrap_or_default();
+            if require_two_factor && user.credentials.totp_secret.is_none() && !ENROLLMENT_ROUTES.contains(&route) {
+                return (StatusCode::FORBIDDEN, "Two-factor enrollment required").into_response();
+            }
+            req.extensions_mut().insert(CurrentUser(user));
+        }
+        None if require_auth => {
+            return (StatusCode::UNAUTHORIZED, "Authentication required").into_response();
+        }
+        None => {}
+    }
+    next.run(req).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+    // Код TOTP либо один из кодов восстановления — если у пользователя включена 2FA
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoginResponse { pub token: String, pub expires_at: DateTime<Utc>, pub user: User }
+
+fn retry_after(status: StatusCode, message: &'static str, until: DateTime<Utc>) -> Response {
+    let secs = (until - Utc::now()).num_seconds().max(1);
+    (status, [(header::RETRY_AFTER, secs.to_string())], message).into_response()
+}
+
+pub async fn login(State(state): State<AuthState>, ClientIp(ip): ClientIp, Json(req): Json<LoginRequest>) -> impl IntoResponse {
+    if let Some(until) = state.guard.ip_blocked_until(ip).await {
+        return retry_after(StatusCode::TOO_MANY_REQUESTS, "Too many failed login attempts", until);
+    }
+    let Some(user) = state.users.find_by_username(&req.username).await else {
+        verify_password(&req.password, &DUMMY_HASH);
+        state.login_failed(ip, None, &req.username, "Unknown user").await;
+        return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response();
+    };
+    let valid = match user.credentials.password_hash.as_deref() {
+        Some(hash) => verify_password(&req.password, hash),
+        None => { verify_password(&req.password, &DUMMY_HASH); false }
+    };
+    // Заблокированная учётная запись отвечает так же, как неверный пароль, и после той же
+    // проверки пароля — иначе по ответу было бы видно, что такое имя существует
+    if user.credentials.locked_until.is_some_and(|until| until > Utc::now()) {
+        state.login_failed(ip, None, &req.username, "Account is locked").await;
+        return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response();
+    }
+    if !valid {
+        state.login_failed(ip, Some(&user), &req.username, "Invalid password").await;
+        return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response();
+    }
+    if user.role == UserRole::Banned { return (StatusCode::FORBIDDEN, "Account is banned").into_response(); }
+    if user.status != UserStatus::Active { return (StatusCode::FORBIDDEN, "Account is not active").into_response(); }
+    if user.credentials.totp_secret.is_some() {
+        // Проверка и отметка об использовании — под одной блокировкой, чтобы код нельзя было повторить
+        let passed = match (req.code.as_deref(), req.recovery_code.as_deref()) {
+            (Some(code), _) => state.users.update(user.id, |u| {
+                let step = totp::verify(u.credentials.totp_secret.as_deref()?, code, u.credentials.totp_last_step)?;
+                u.credentials.totp_last_step = Some(step);
+                Some(())
+            }).await.flatten().is_some(),
+            // Хеш ищется без блокировки, а гасится под ней: если код уже использован параллельно, его не будет
+            (None, Some(recovery)) => match totp::find_recovery_code(&user.credentials.recovery_codes, recovery).cloned() {
+                Some(hashed) => state.users.update(user.id, |u| {
+                    let before = u.credentials.recovery_codes.len();
+                    u.credentials.recovery_codes.retain(|c| *c != hashed);
+                    u.credentials.recovery_codes.len() != before
+                }).await.unwrap_or(false),
+                None => false,
+            },
+            (None, None) => return (StatusCode::UNAUTHORIZED, "Two-factor code required").into_response(),
+        };
+        if !passed {
+            state.login_failed(ip, Some(&user), &req.username, "Invalid two-factor code").await;
+            return (StatusCode::UNAUTHORIZED, "Invalid two-factor code").into_response();
+        }
+    }
+    if user.credentials.failed_logins > 0 {
+        state.users.update(user.id, |u| u.credentials.failed_logins = 0).await;
+    }
+
+    let hours = state.settings.inner.read().await.security.session_timeout.max(1);
+    let (token, session) = state.sessions.create(user.id, Duration::hours(hours as i64)).await;
+    let cookie = session_cookie(&token, hours as i64 * 3600, state.secure_cookies);
+    ([(header::SET_COOKIE, cookie)], Json(LoginResponse { token, expires_at: session.expires_at, user })).into_response()
+}
+
+pub async fn logout(State(state): State<AuthState>, headers: HeaderMap) -> impl IntoResponse {
+    if let Some(token) = request_token(&headers) { state.sessions.revoke(&token).await; }
+    let cookie = session_cookie("", 0, state.secure_cookies);
+    ([(header::SET_COOKIE, cookie)], StatusCode::NO_CONTENT)
+}
+
+pub async fn me(CurrentUser(user): CurrentUser) -> Json<User> {
+    Json(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    const PASSWORD: &str = "correct horse";
+    const RECOVERY: &str = "abcd-efgh-ijkl-mnop";
+
+    async fn state() -> (AuthState, User) {
+        let db: Db = Arc::new(MemoryStorage::default());
+        let state = AuthState {
+            users: UsersStore::open(&db),
+            sessions: SessionStore::open(&db),
+            settings: SettingsStore::open(&db),
+            logs: LogsStore::open_in(&db, None),
+            guard: LoginGuard::default(),
+            secure_cookies: true,
+        };
+        let admin = state.users.find_by_username("admin").await.unwrap();
+        state.users.update(admin.id, |u| {
+            u.credentials.password_hash = Some(hash_password(PASSWORD));
+            u.credentials.totp_secret = Some(totp::generate_secret());
+            u.credentials.recovery_codes = vec![totp::hash_recovery_code(RECOVERY)];
+        }).await;
+        (state, admin)
+    }
+
+    async fn attempt(state: &AuthState, password: &str, recovery_code: Option<&str>) -> StatusCode {
+        let req = LoginRequest { username: "admin".into(), password: password.into(), code: None, recovery_code: recovery_code.map(str::to_string) };
+        login(State(state.clone()), ClientIp(IpAddr::V4(Ipv4Addr::LOCALHOST)), Json(req)).await.into_response().status()
+    }
+
+    async fn login_with(state: &AuthState, recovery_code: Option<&str>) -> StatusCode {
+        attempt(state, PASSWORD, recovery_code).await
+    }
+
+    #[test]
+    fn session_cookie_is_secure_unless_disabled() {
+        assert_eq!(session_cookie("t", 60, true), "dc_session=t; HttpOnly; Secure; SameSite=Strict; Path=/; Max-Age=60");
+        assert_eq!(session_cookie("", 0, false), "dc_session=; HttpOnly; SameSite=Strict; Path=/; Max-Age=0");
+    }
+
+    #[tokio::test]
+    async fn recovery_code_signs_in_once() {
+        let (state, admin) = state().await;
+        assert_eq!(login_with(&state, None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(login_with(&state, Some("ABCD EFGH IJKL MNOP")).await, StatusCode::OK);
+        assert!(state.users.get(admin.id).await.unwrap().credentials.recovery_codes.is_empty());
+        assert_eq!(login_with(&state, Some(RECOVERY)).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn lockout_hides_account_and_unlock_clears_ip_blocks() {
+        let (state, admin) = state().await;
+        state.users.update(admin.id, |u| u.credentials.totp_secret = None).await;
+        state.settings.update(|cfg| cfg.security.max_login_attempts = 1).await;
+        let locked = || async { state.users.get(admin.id).await.unwrap().credentials.locked_until.is_some() };
+        // Блокирует попытка сверх лимита, а не последняя допустимая
+        assert_eq!(attempt(&state, "wrong", None).await, StatusCode::UNAUTHORIZED);
+        assert!(!locked().await);
+        assert_eq!(attempt(&state, "wrong", None).await, StatusCode::UNAUTHORIZED);
+        assert!(locked().await);
+        // Верный пароль при блокировке даёт тот же ответ, что и неверный
+        assert_eq!(attempt(&state, PASSWORD, None).await, StatusCode::UNAUTHORIZED);
+
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        while state.guard.ip_blocked_until(ip).await.is_none() { attempt(&state, "wrong", None).await; }
+        assert_eq!(attempt(&state, PASSWORD, None).await, StatusCode::TOO_MANY_REQUESTS);
+
+        let res = crate::users::unlock_user(State(state.clone()), axum::extract::Path(admin.id)).await.into_response();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert!(!locked().await);
+        assert!(state.guard.ip_blocked_until(ip).await.is_none());
+        assert_eq!(attempt(&state, PASSWORD, None).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn ingest_token_only_opens_log_ingestion() {
+        use axum::{body::Body, middleware, routing::{get, post}, Router};
+        use tower::Service;
+        let (state, _) = state().await;
+        let (token, info) = state.logs.tokens().create("billing", "admin").await;
+        let mut app = Router::new()
+            .route("/api/logs", get(|| async {}).post(|| async { StatusCode::CREATED }))
+            .route("/api/logs/batch", post(|| async { StatusCode::CREATED }))
+            .route_layer(middleware::from_fn(crate::permissions::authorize))
+            .route_layer(middleware::from_fn_with_state(state.clone(), require_auth));
+        let mut call = async |method: &str, uri: &str, token: Option<&str>| {
+            let mut req = Request::builder().method(method).uri(uri);
+            if let Some(token) = token { req = req.header(header::AUTHORIZATION, format!("Bearer {}", token)); }
+            app.call(req.body(Body::empty()).unwrap()).await.unwrap().status()
+        };
+        assert_eq!(call("POST", "/api/logs", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(call("POST", "/api/logs", Some(&token)).await, StatusCode::CREATED);
+        assert_eq!(call("POST", "/api/logs/batch", Some(&token)).await, StatusCode::CREATED);
+        assert_eq!(call("GET", "/api/logs", Some(&token)).await, StatusCode::FORBIDDEN);
+        assert_eq!(call("POST", "/api/logs", Some("dci_forged")).await, StatusCode::UNAUTHORIZED);
+        state.logs.tokens().revoke(info.id).await;
+        assert_eq!(call("POST", "/api/logs", Some(&token)).await, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -1,5 +1,14 @@
-use axum::Json;
+use std::{collections::HashMap, ffi::CString, sync::Arc, time::{Duration, Instant}};
+use axum::{extract::State, Json};
 use serde::Serialize;
+use tokio::sync::RwLock;
+use crate::{auth::SessionStore, metrics::{Metrics, RouteMetrics}};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+// Если скорость интерфейса неизвестна (виртуальные сетевые карты), считаем её гигабитной
+const DEFAULT_LINK_MBPS: f64 = 1000.0;
+// Пользователь считается активным, если его сессия использовалась за это время
+const ACTIVE_WINDOW_MINUTES: i64 = 15;
 
 #[derive(Debug, Serialize)]
 pub struct DashboardStats {
@@ -13,19 +22,153 @@ pub struct DashboardStats {
     pub errors: u32,
 }
 
-pub async fn get_dashboard() -> Json<DashboardStats> {
-    // Здесь можно подключить sysinfo, пока — мок-данные
-    Json(DashboardStats {
-        cpu: 65,
-        memory: 78,
-        disk: 45,
-        network: 23,
-        uptime: "15d 4h 32m".into(),
-        active_users: 1247,
-        requests: 89432,
-        errors: 12,
-    })
+// Последний замер хоста, проценты 0..=100
+#[derive(Debug, Clone, Default)]
+pub struct HostSample { pub cpu: u8, pub memory: u8, pub disk: u8, pub network: u8, pub uptime_secs: u64 }
+
+#[derive(Debug, Clone)]
+pub struct DashboardStore { latest: Arc<RwLock<HostSample>>, metrics: Metrics, sessions: SessionStore }
+
+impl DashboardStore {
+    // Запускает фоновый сбор метрик из /proc и statvfs
+    pub fn spawn_sampler(metrics: Metrics, sessions: SessionStore) -> Self {
+        let store = Self { latest: Arc::default(), metrics, sessions };
+        let latest = store.latest.clone();
+        tokio::spawn(async move {
+            let mut sampler = Sampler::default();
+            let mut tick = tokio::time::interval(SAMPLE_INTERVAL);
+            loop {
+                tick.tick().await;
+                let sample = sampler.sample();
+                *latest.write().await = sample;
+            }
+        });
+        store
+    }
+}
+
+fn percent(part: f64, total: f64) -> u8 {
+    if total <= 0.0 { return 0; }
+    (part / total * 100.0).round().clamp(0.0, 100.0) as u8
 }
 
+// Счётчики прошлого замера: проценты CPU и сети считаются по разнице
+#[derive(Default)]
+struct Sampler { cpu: Option<(u64, u64)>, net: Option<(u64, Instant)> }
+
+impl Sampler {
+    fn sample(&mut self) -> HostSample {
+        HostSample {
+            cpu: self.cpu_percent(),
+            memory: memory_percent(),
+            disk: disk_percent("/"),
+            network: self.network_percent(),
+            uptime_secs: uptime_secs(),
+        }
+    }
+
+    // Первая строка /proc/stat: user nice system idle iowait irq softirq steal ...
+    fn cpu_percent(&mut self) -> u8 {
+        let Ok(stat) = std::fs::read_to_string("/proc/stat") else { return 0 };
+        let Some(line) = stat.lines().next() else { return 0 };
+        let fields: Vec<u64> = line.split_whitespace().skip(1).filter_map(|v| v.parse().ok()).collect();
+        if fields.len() < 4 { return 0; }
+        let idle = fields[3] + fields.get(4).copied().unwrap_or(0);
+        let total: u64 = fields.iter().take(8).sum();
+        let prev = self.cpu.replace((idle, total));
+        match prev {
+            Some((prev_idle, prev_total)) if total > prev_total => {
+                let busy = (total - prev_total).saturating_sub(idle.saturating_sub(prev_idle));
+                percent(busy as f64, (total - prev_total) as f64)
+            }
+            _ => 0,
+        }
+    }
 
+    // Загрузка самого занятого направления (rx/tx) относительно суммарной скорости интерфейсов
+    fn network_percent(&mut self) -> u8 {
+        let Ok(dev) = std::fs::read_to_string("/proc/net/dev") else { return 0 };
+        let mut bytes = 0u64;
+        let mut capacity_mbps = 0.0;
+        for line in dev.lines().skip(2) {
+            let Some((iface, counters)) = line.split_once(':') else { continue };
+            let iface = iface.trim();
+            if iface == "lo" { continue; }
+            let counters: Vec<u64> = counters.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+            if counters.len() < 9 { continue; }
+            bytes += counters[0].max(counters[8]);
+            capacity_mbps += std::fs::read_to_string(format!("/sys/class/net/{}/speed", iface)).ok()
+                .and_then(|s| s.trim().parse::<f64>().ok())
+                .filter(|mbps| *mbps > 0.0)
+                .unwrap_or(DEFAULT_LINK_MBPS);
+        }
+        let now = Instant::now();
+        match self.net.replace((bytes, now)) {
+            Some((prev_bytes, prev_at)) if bytes >= prev_bytes => {
+                let secs = now.duration_since(prev_at).as_secs_f64();
+                let bits_per_sec = (bytes - prev_bytes) as f64 * 8.0 / secs.max(0.001);
+                percent(bits_per_sec, capacity_mbps * 1_000_000.0)
+            }
+            _ => 0,
+        }
+    }
+}
+
+fn memory_percent() -> u8 {
+    let Ok(meminfo) = std::fs::read_to_string("/proc/meminfo") else { return 0 };
+    let values: HashMap<&str, f64> = meminfo.lines()
+        .filter_map(|l| {
+            let (key, rest) = l.split_once(':')?;
+            Some((key, rest.split_whitespace().next()?.parse().ok()?))
+        })
+        .collect();
+    match (values.get("MemTotal"), values.get("MemAvailable")) {
+        (Some(total), Some(available)) => percent(total - available, *total),
+        _ => 0,
+    }
+}
 
+fn disk_percent(path: &str) -> u8 {
+    let Ok(path) = CString::new(path) else { return 0 };
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: path — корректная C-строка, st — выделенная структура нужного размера
+    if unsafe { libc::statvfs(path.as_ptr(), &mut st) } != 0 { return 0; }
+    let total = st.f_blocks as f64 * st.f_frsize as f64;
+    let available = st.f_bavail as f64 * st.f_frsize as f64;
+    let free = st.f_bfree as f64 * st.f_frsize as f64;
+    // Как df: занято / (занято + доступно обычному пользователю)
+    let used = total - free;
+    percent(used, used + available)
+}
+
+fn uptime_secs() -> u64 {
+    std::fs::read_to_string("/proc/uptime").ok()
+        .and_then(|s| s.split_whitespace().next()?.parse::<f64>().ok())
+        .map(|secs| secs as u64)
+        .unwrap_or(0)
+}
+
+fn format_uptime(secs: u64) -> String {
+    format!("{}d {}h {}m", secs / 86_400, secs % 86_400 / 3600, secs % 3600 / 60)
+}
+
+pub async fn get_dashboard(State(store): State<DashboardStore>) -> Json<DashboardStats> {
+    let host = store.latest.read().await.clone();
+    let traffic = store.metrics.totals();
+    let active_users = store.sessions.active_users(chrono::Duration::minutes(ACTIVE_WINDOW_MINUTES)).await;
+    Json(DashboardStats {
+        cpu: host.cpu,
+        memory: host.memory,
+        disk: host.disk,
+        network: host.network,
+        uptime: format_uptime(host.uptime_secs),
+        active_users: active_users as u32,
+        requests: traffic.requests,
+        errors: (traffic.client_errors + traffic.server_errors).min(u32::MAX as u64) as u32,
+    })
+}
+
+// Разбивка счётчиков по маршрутам, самые нагруженные первыми
+pub async fn route_metrics(State(store): State<DashboardStore>) -> Json<Vec<RouteMetrics>> {
+    Json(store.metrics.by_route())
+}
@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::RwLock;
 use uuid::Uuid;
+use crate::storage::{Collection, Db};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -20,11 +21,20 @@ pub struct FileNode {
     pub parent: Option<Uuid>,
 }
 
-#[derive(Debug, Default, Clone)]
-pub struct FsStore { pub nodes: Arc<RwLock<HashMap<Uuid, FileNode>>> }
+#[derive(Debug, Clone)]
+pub struct FsStore { pub nodes: Arc<RwLock<HashMap<Uuid, FileNode>>>, db: Collection }
 
 impl FsStore {
-    pub fn new_mock() -> Self {
+    pub fn open(db: &Db) -> Self {
+        let db = Collection::new(db, "files");
+        let map = db.load_or_seed(|n: &FileNode| n.id.to_string(), || Self::mock().into_values().collect())
+            .into_iter()
+            .map(|n| (n.id, n))
+            .collect();
+        Self { nodes: Arc::new(RwLock::new(map)), db }
+    }
+
+    fn mock() -> HashMap<Uuid, FileNode> {
         let mut map = HashMap::new();
         let root_public = Uuid::new_v4();
         let root_src = Uuid::new_v4();
@@ -32,7 +42,7 @@ impl FsStore {
         map.insert(root_src, FileNode { id: root_src, name: "src".into(), node_type: NodeType::Folder, size: None, modified: "1 час назад".into(), extension: None, content: None, parent: None });
         let pkg = Uuid::new_v4();
         map.insert(pkg, FileNode { id: pkg, name: "package.json".into(), node_type: NodeType::File, size: Some("2.1 KB".into()), modified: "3 дня назад".into(), extension: Some("json".into()), content: Some("{\n  \"name\": \"project\"\n}".into()), parent: None });
-        Self { nodes: Arc::new(RwLock::new(map)) }
+        map
     }
 }
 
@@ -48,6 +58,7 @@ pub async fn create(State(store): State<FsStore>, Json(req): Json<CreateNode>) -
     let id = Uuid::new_v4();
     let node = FileNode { id, name: req.name, node_type: req.node_type, size: Some("0 B".into()), modified: "только что".into(), extension: None, content: Some(String::new()), parent: req.parent };
     map.insert(id, node.clone());
+    store.db.put(&id.to_string(), &node);
     Json(node)
 }
 
@@ -56,13 +67,13 @@ pub struct UpdateFile { pub content: String }
 
 pub async fn save(State(store): State<FsStore>, Path(id): Path<Uuid>, Json(req): Json<UpdateFile>) -> impl IntoResponse {
     let mut map = store.nodes.write().await;
-    if let Some(node) = map.get_mut(&id) { node.content = Some(req.content); node.modified = "только что".into(); return StatusCode::OK; }
+    if let Some(node) = map.get_mut(&id) { node.content = Some(req.content); node.modified = "только что".into(); store.db.put(&id.to_string(), node); return StatusCode::OK; }
     StatusCode::NOT_FOUND
 }
 
 pub async fn remove(State(store): State<FsStore>, Path(id): Path<Uuid>) -> impl IntoResponse {
     let mut map = store.nodes.write().await;
-    if map.remove(&id).is_some() { return StatusCode::NO_CONTENT; }
+    if map.remove(&id).is_some() { store.db.remove(&id.to_string()); return StatusCode::NO_CONTENT; }
     StatusCode::NOT_FOUND
 }
 
@@ -0,0 +1,171 @@
+use std::net::{IpAddr, SocketAddr};
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, Request, State},
+    http::{request::Parts, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use crate::settings::{Security, SettingsStore};
+
+// Одиночный адрес ("10.0.0.5", "::1") или диапазон CIDR ("10.0.0.0/8", "2001:db8::/32")
+pub fn parse_rule(rule: &str) -> Result<IpNet, String> {
+    let rule = rule.trim();
+    rule.parse::<IpNet>()
+        .or_else(|_| rule.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("invalid IP address or CIDR range: {:?}", rule))
+}
+
+pub fn parse_rules(rules: &[String]) -> Result<Vec<IpNet>, String> {
+    rules.iter().map(|r| parse_rule(r)).collect()
+}
+
+// Настоящий адрес клиента. X-Forwarded-For учитывается только если соединение пришло
+// от доверенного прокси; цепочка разбирается справа налево до первого недоверенного адреса.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpNet]) -> IpAddr {
+    let trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+    if !trusted(&peer) { return peer; }
+    let forwarded: Vec<IpAddr> = headers.get_all("x-forwarded-for").iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|ip| ip.trim().parse().ok())
+        .collect();
+    let mut client = peer;
+    for ip in forwarded.into_iter().rev() {
+        client = ip;
+        if !trusted(&ip) { break; }
+    }
+    client
+}
+
+// Проверка настроек перед сохранением: allowed_ips и trusted_proxies должны разбираться
+pub fn validate(security: &Security) -> Result<(), String> {
+    parse_rules(&security.allowed_ips).map_err(|e| format!("allowed_ips: {}", e))?;
+    parse_rules(&security.trusted_proxies).map_err(|e| format!("trusted_proxies: {}", e))?;
+    Ok(())
+}
+
+pub fn is_allowed(security: &Security, ip: IpAddr) -> bool {
+    !security.ip_whitelist || parse_rules(&security.allowed_ips).is_ok_and(|rules| rules.iter().any(|net| net.contains(&ip)))
+}
+
+// Адрес клиента, определённый middleware `filter`
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(ip) = parts.extensions.get::<ClientIp>() { return Ok(*ip); }
+        parts.extensions.get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| ClientIp(addr.ip()))
+            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, "Client address unavailable"))
+    }
+}
+
+// Настройки читаются на каждый запрос, поэтому изменения через update_settings
+// применяются сразу, без перезапуска
+pub async fn filter(State(settings): State<SettingsStore>, mut req: Request, next: Next) -> Response {
+    let Some(ConnectInfo(peer)) = req.extensions().get::<ConnectInfo<SocketAddr>>().copied() else {
+        return next.run(req).await;
+    };
+    let (ip, allowed) = {
+        let cfg = settings.inner.read().await;
+        let proxies = parse_rules(&cfg.security.trusted_proxies).unwrap_or_default();
+        let ip = client_ip(peer.ip(), req.headers(), &proxies);
+        (ip, is_allowed(&cfg.security, ip))
+    };
+    if !allowed {
+        tracing::warn!(%ip, category = "security", "Request rejected by IP allowlist");
+        return (StatusCode::FORBIDDEN, "IP address not allowed").into_response();
+    }
+    req.extensions_mut().insert(ClientIp(ip));
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use axum::{extract::State, Json};
+    use super::*;
+    use crate::{settings::update_settings, storage::{Db, MemoryStorage}};
+
+    fn ip(s: &str) -> IpAddr { s.parse().unwrap() }
+
+    fn xff(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", value.parse().unwrap());
+        headers
+    }
+
+    fn security(whitelist: bool, allowed: &[&str]) -> Security {
+        Security {
+            enable_ssl: false, require_two_factor: false, session_timeout: 24, max_login_attempts: 5,
+            ip_whitelist: whitelist, allowed_ips: allowed.iter().map(|s| s.to_string()).collect(), trusted_proxies: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parses_addresses_and_cidr_ranges() {
+        assert!(parse_rule(" 10.0.0.5 ").unwrap().contains(&ip("10.0.0.5")));
+        assert!(!parse_rule("10.0.0.5").unwrap().contains(&ip("10.0.0.6")));
+        let v6 = parse_rule("2001:db8::/32").unwrap();
+        assert!(v6.contains(&ip("2001:db8:ffff::1")));
+        assert!(!v6.contains(&ip("2001:db9::1")));
+        assert!(!v6.contains(&ip("10.0.0.1")));
+        assert!(parse_rule("::1").unwrap().contains(&ip("::1")));
+        for bad in ["", "10.0.0.256", "10.0.0.0/33", "2001:db8::/129", "example.com", "10.0.0.0/8/8"] {
+            assert!(parse_rule(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn ignores_forwarded_for_from_untrusted_peer() {
+        let proxies = parse_rules(&["10.0.0.0/8".into()]).unwrap();
+        assert_eq!(client_ip(ip("203.0.113.7"), &xff("127.0.0.1"), &proxies), ip("203.0.113.7"));
+        assert_eq!(client_ip(ip("203.0.113.7"), &xff("127.0.0.1"), &[]), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn walks_multi_hop_forwarded_for_to_first_untrusted() {
+        let proxies = parse_rules(&["10.0.0.0/8".into(), "fd00::/8".into()]).unwrap();
+        // Клиент подставил свой адрес в начало цепочки — берётся адрес, который увидел первый прокси
+        assert_eq!(client_ip(ip("10.0.0.1"), &xff("1.1.1.1, 198.51.100.4, 10.0.0.2"), &proxies), ip("198.51.100.4"));
+        assert_eq!(client_ip(ip("fd00::1"), &xff("2001:db8::5, fd00::2"), &proxies), ip("2001:db8::5"));
+        // Несколько заголовков читаются как одна цепочка, мусор пропускается
+        let mut headers = xff("198.51.100.4, garbage");
+        headers.append("x-forwarded-for", "10.0.0.3".parse().unwrap());
+        assert_eq!(client_ip(ip("10.0.0.1"), &headers, &proxies), ip("198.51.100.4"));
+        // Вся цепочка из прокси — берётся самый дальний
+        assert_eq!(client_ip(ip("10.0.0.1"), &xff("10.0.0.9, 10.0.0.2"), &proxies), ip("10.0.0.9"));
+        assert_eq!(client_ip(ip("10.0.0.1"), &HeaderMap::new(), &proxies), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn allowlist_applies_only_when_enabled() {
+        let rules = ["192.168.1.0/24", "2001:db8::/32"];
+        assert!(is_allowed(&security(false, &rules), ip("203.0.113.7")));
+        assert!(is_allowed(&security(true, &rules), ip("192.168.1.20")));
+        assert!(is_allowed(&security(true, &rules), ip("2001:db8::1")));
+        assert!(!is_allowed(&security(true, &rules), ip("192.168.2.1")));
+        assert!(!is_allowed(&security(true, &[]), ip("127.0.0.1")));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_entries_at_save_time() {
+        let db: Db = Arc::new(MemoryStorage::default());
+        let settings = SettingsStore::open(&db);
+        let mut cfg = settings.inner.read().await.clone();
+        cfg.security.allowed_ips = vec!["10.0.0.0/8".into(), "10.0.0.300".into()];
+        let res = update_settings(State(settings.clone()), ClientIp(ip("10.0.0.1")), Json(cfg.clone())).await.into_response();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        cfg.security.allowed_ips = vec!["10.0.0.0/8".into()];
+        cfg.security.trusted_proxies = vec!["proxy.local".into()];
+        let res = update_settings(State(settings.clone()), ClientIp(ip("10.0.0.1")), Json(cfg)).await.into_response();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert!(settings.inner.read().await.security.trusted_proxies.is_empty());
+    }
+}
@@ -0,0 +1,20 @@
+// Модули сервера. Приложение собирается в main.rs; отдельная библиотека нужна,
+// чтобы бенчмарки (benches/) работали с теми же типами, что и сервер.
+pub mod news;
+pub mod users;
+pub mod logs;
+pub mod dashboard;
+pub mod settings;
+pub mod terminal;
+pub mod files;
+pub mod storage;
+pub mod auth;
+pub mod permissions;
+pub mod totp;
+pub mod lockout;
+pub mod ip_filter;
+pub mod rate_limit;
+pub mod maintenance;
+pub mod metrics;
+pub mod alerts;
+pub mod request_id;
@@ -0,0 +1,81 @@
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::RwLock;
+
+// Сколько длится блокировка и за какое окно считаются неудачные попытки
+pub const LOCKOUT: Duration = Duration::minutes(15);
+// С одного IP может входить целый офис за NAT, поэтому лимит по адресу мягче
+const IP_LIMIT_FACTOR: u32 = 4;
+
+// usernames — сколько неудачных попыток пришлось на каждое имя; разблокировка
+// пользователя вычитает только его попытки
+#[derive(Debug, Clone, Default)]
+struct IpAttempts { failures: u32, window_start: DateTime<Utc>, blocked_until: Option<DateTime<Utc>>, usernames: HashMap<String, u32> }
+
+fn ip_limit(max_attempts: u8) -> u32 { max_attempts as u32 * IP_LIMIT_FACTOR }
+
+// Счётчики неудачных входов по IP. Счётчики по учётным записям живут
+// в `Credentials` и сохраняются вместе с пользователем.
+#[derive(Debug, Clone, Default)]
+pub struct LoginGuard { ips: Arc<RwLock<HashMap<IpAddr, IpAttempts>>> }
+
+impl LoginGuard {
+    pub async fn ip_blocked_until(&self, ip: IpAddr) -> Option<DateTime<Utc>> {
+        let now = Utc::now();
+        self.ips.read().await.get(&ip).and_then(|a| a.blocked_until).filter(|until| *until > now)
+    }
+
+    // Возвращает время окончания блокировки, если этой попыткой IP превысил лимит.
+    // max_attempts = 0 отключает блокировку.
+    pub async fn ip_failed(&self, ip: IpAddr, username: &str, max_attempts: u8) -> Option<DateTime<Utc>> {
+        if max_attempts == 0 { return None; }
+        let now = Utc::now();
+        let mut ips = self.ips.write().await;
+        ips.retain(|_, a| a.window_start + LOCKOUT > now || a.blocked_until.is_some_and(|u| u > now));
+        let entry = ips.entry(ip).or_insert_with(|| IpAttempts { window_start: now, ..IpAttempts::default() });
+        if entry.window_start + LOCKOUT <= now { *entry = IpAttempts { window_start: now, ..IpAttempts::default() }; }
+        entry.failures += 1;
+        *entry.usernames.entry(username.to_string()).or_default() += 1;
+        if entry.failures > ip_limit(max_attempts) && entry.blocked_until.is_none() {
+            entry.blocked_until = Some(now + LOCKOUT);
+            return entry.blocked_until;
+        }
+        None
+    }
+
+    // Вычитает попытки под этим именем со всех адресов. Блокировка адреса снимается,
+    // только если без них он уже не превышает лимит — перебор других имён её сохраняет.
+    pub async fn clear_user(&self, username: &str, max_attempts: u8) {
+        let mut ips = self.ips.write().await;
+        for attempts in ips.values_mut() {
+            let Some(count) = attempts.usernames.remove(username) else { continue };
+            attempts.failures = attempts.failures.saturating_sub(count);
+            if attempts.failures <= ip_limit(max_attempts) { attempts.blocked_until = None; }
+        }
+        ips.retain(|_, a| a.failures > 0 || a.blocked_until.is_some());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use super::*;
+
+    #[tokio::test]
+    async fn unlocking_one_user_keeps_blocks_earned_on_other_names() {
+        let guard = LoginGuard::default();
+        let office = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let sprayer = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        for _ in 0..5 { guard.ip_failed(office, "alice", 1).await; }
+        for name in ["alice", "bob", "carol", "dave", "erin", "frank"] { guard.ip_failed(sprayer, name, 1).await; }
+        assert!(guard.ip_blocked_until(office).await.is_some());
+        assert!(guard.ip_blocked_until(sprayer).await.is_some());
+
+        guard.clear_user("alice", 1).await;
+        assert!(guard.ip_blocked_until(office).await.is_none());
+        // У адреса остаются 5 попыток под другими именами — больше лимита в 4
+        assert!(guard.ip_blocked_until(sprayer).await.is_some());
+        guard.clear_user("bob", 1).await;
+        assert!(guard.ip_blocked_until(sprayer).await.is_none());
+    }
+}
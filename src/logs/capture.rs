@@ -0,0 +1,198 @@
+use std::{fmt::Write as _, sync::{atomic::{AtomicU8, Ordering}, Arc}};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{field::{Field, Visit}, span, subscriber::Interest, Event, Level, Metadata, Subscriber};
+use tracing_subscriber::{filter::Filtered, layer::{Context, Filter}, registry::LookupSpan, Layer};
+use super::{LogCategory, LogEntry, LogLevel, LogsStore};
+use crate::settings::SettingsStore;
+
+// События, ожидающие записи в журнал; при переполнении новые отбрасываются
+const QUEUE_CAPACITY: usize = 4096;
+const BATCH: usize = 256;
+// Значения таких полей не попадают в журнал, который видят все с доступом к панели
+const SECRET_FIELDS: [&str; 3] = ["password", "secret", "token"];
+// События самого журнала не собираются, иначе ошибка записи породит бесконечный цикл
+const OWN_TARGET: &str = "untitled::logs";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureLevel { Off, Error, #[default] Warn, Info, Debug }
+
+impl CaptureLevel {
+    fn allows(self, level: &Level) -> bool {
+        let required = match *level {
+            Level::ERROR => CaptureLevel::Error,
+            Level::WARN => CaptureLevel::Warn,
+            Level::INFO => CaptureLevel::Info,
+            _ => CaptureLevel::Debug,
+        };
+        self >= required
+    }
+
+    fn from_u8(v: u8) -> Self {
+        [CaptureLevel::Off, CaptureLevel::Error, CaptureLevel::Warn, CaptureLevel::Info, CaptureLevel::Debug].get(v as usize).copied().unwrap_or_default()
+    }
+}
+
+// Слой tracing, превращающий события сервера в записи журнала. target становится
+// source, поля событий и их спанов — details, а поля user/ip/request_id — одноимёнными полями записи.
+pub struct CaptureLayer { tx: mpsc::Sender<LogEntry> }
+
+// Уровень меняется в настройках на лету, поэтому решение принимается для каждого события
+pub struct CaptureFilter { level: Arc<AtomicU8> }
+
+// Вторая половина слоя: подключается к хранилищу журнала, когда оно открыто
+pub struct CaptureHandle { rx: mpsc::Receiver<LogEntry>, level: Arc<AtomicU8> }
+
+impl CaptureLayer {
+    pub fn new<S>() -> (Filtered<Self, CaptureFilter, S>, CaptureHandle)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        let level = Arc::new(AtomicU8::new(CaptureLevel::default() as u8));
+        (Self { tx }.with_filter(CaptureFilter { level: level.clone() }), CaptureHandle { rx, level })
+    }
+}
+
+impl<S> Filter<S> for CaptureFilter {
+    fn enabled(&self, metadata: &Metadata<'_>, _: &Context<'_, S>) -> bool {
+        // Спаны нужны всегда — из них берутся поля для событий внутри
+        if !metadata.is_event() { return true; }
+        let level = CaptureLevel::from_u8(self.level.load(Ordering::Relaxed));
+        level.allows(metadata.level()) && !metadata.target().starts_with(OWN_TARGET)
+    }
+
+    fn callsite_enabled(&self, _: &'static Metadata<'static>) -> Interest { Interest::sometimes() }
+}
+
+impl CaptureHandle {
+    // События, накопившиеся до открытия хранилища, записываются первыми
+    pub fn attach(self, store: LogsStore, settings: SettingsStore) {
+        let CaptureHandle { mut rx, level } = self;
+        let mut changes = settings.subscribe();
+        level.store(changes.borrow_and_update().logs.capture_level as u8, Ordering::Relaxed);
+        tokio::spawn(async move {
+            while changes.changed().await.is_ok() {
+                level.store(changes.borrow_and_update().logs.capture_level as u8, Ordering::Relaxed);
+            }
+        });
+        tokio::spawn(async move {
+            let mut batch = Vec::with_capacity(BATCH);
+            while rx.recv_many(&mut batch, BATCH).await > 0 {
+                store.append_many(std::mem::take(&mut batch)).await;
+            }
+        });
+    }
+}
+
+// Поля спана, сохранённые при его создании
+#[derive(Default)]
+struct SpanFields(Fields);
+
+#[derive(Default)]
+struct Fields { message: Option<String>, user: Option<String>, ip: Option<String>, request_id: Option<String>, category: Option<LogCategory>, rest: Vec<(&'static str, String)> }
+
+impl Fields {
+    fn set(&mut self, field: &Field, value: String) {
+        let value = if SECRET_FIELDS.iter().any(|s| field.name().contains(s)) { "[redacted]".to_string() } else { value };
+        match field.name() {
+            "message" => self.message = Some(value),
+            "user" | "username" => self.user = Some(value),
+            "ip" | "client_ip" => self.ip = Some(value),
+            "request_id" => self.request_id = Some(value),
+            "category" => match LogCategory::parse(&value) {
+                Some(category) => self.category = Some(category),
+                None => self.rest.push(("category", value)),
+            },
+            name => {
+                self.rest.retain(|(n, _)| *n != name);
+                self.rest.push((name, value));
+            }
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) { self.set(field, value.to_string()); }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) { self.set(field, format!("{:?}", value)); }
+}
+
+impl<S> Layer<S> for CaptureLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut fields = SpanFields::default();
+        attrs.record(&mut fields.0);
+        span.extensions_mut().insert(fields);
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut ext = span.extensions_mut();
+        if let Some(fields) = ext.get_mut::<SpanFields>() { values.record(&mut fields.0); }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let level = match *meta.level() {
+            Level::ERROR => LogLevel::Error,
+            Level::WARN => LogLevel::Warning,
+            Level::INFO => LogLevel::Info,
+            _ => LogLevel::Debug,
+        };
+        let mut entry = LogEntry::new(level, fields.category.unwrap_or(LogCategory::System), fields.message.unwrap_or_default(), meta.target());
+        let mut details = String::new();
+        for (name, value) in &fields.rest { let _ = writeln!(details, "{}={}", name, value); }
+        entry.user = fields.user;
+        entry.ip = fields.ip;
+        if fields.request_id.is_some() { entry.request_id = fields.request_id; }
+        // Поля спанов — от ближайшего к корню; поля самого события важнее
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope {
+                let ext = span.extensions();
+                let Some(SpanFields(span_fields)) = ext.get::<SpanFields>() else { continue };
+                if entry.user.is_none() { entry.user = span_fields.user.clone(); }
+                if entry.ip.is_none() { entry.ip = span_fields.ip.clone(); }
+                if entry.request_id.is_none() { entry.request_id = span_fields.request_id.clone(); }
+                for (name, value) in &span_fields.rest { let _ = writeln!(details, "{}.{}={}", span.name(), name, value); }
+            }
+        }
+        entry.details = Some(details.trim_end().to_string()).filter(|d| !d.is_empty());
+        if entry.message.is_empty() { entry.message = meta.name().to_string(); }
+        let _ = self.tx.try_send(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::layer::SubscriberExt;
+    use super::*;
+
+    #[test]
+    fn secret_fields_are_redacted_in_events_and_spans() {
+        let (layer, mut handle) = CaptureLayer::new();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            // Свой модуль теста лежит под OWN_TARGET, поэтому target задан явно
+            let span = tracing::warn_span!(target: "untitled::auth", "login", client_secret = "s3cr3t", username = "admin");
+            let _guard = span.enter();
+            tracing::warn!(target: "untitled::auth", password = "hunter2", api_token = "dci_abc", attempt = 3, "sign-in failed");
+        });
+
+        let entry = handle.rx.try_recv().unwrap();
+        assert_eq!(entry.message, "sign-in failed");
+        assert_eq!(entry.user.as_deref(), Some("admin"));
+        let details = entry.details.unwrap();
+        for secret in ["hunter2", "dci_abc", "s3cr3t"] { assert!(!details.contains(secret), "{} leaked: {}", secret, details); }
+        assert!(details.contains("password=[redacted]"));
+        assert!(details.contains("api_token=[redacted]"));
+        assert!(details.contains("login.client_secret=[redacted]"));
+        assert!(details.contains("attempt=3"));
+    }
+}
@@ -0,0 +1,252 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{BuildHasher, RandomState},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use super::{LogCategory, LogEntry, LogLevel};
+use crate::settings::LogsConfig;
+
+// Больше открытых окон не держим: при потоке уникальных сообщений вытесняются самые давно повторявшиеся
+const MAX_GROUPS: usize = 10_000;
+
+// Одинаковые записи, схлопнутые в первую из них
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Repeat {
+    // Всего записей вместе с первой
+    pub count: u64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+// Открытое окно. Сама запись не хранится — только то, что нужно для ответа и счётчика.
+#[derive(Debug)]
+struct Group {
+    seq: u64,
+    id: String,
+    timestamp: DateTime<Utc>,
+    opened: Instant,
+    // Номер создания (ключ очереди истечения) и последнего обращения (ключ вытеснения)
+    created: u64,
+    used: u64,
+    repeat: Option<Repeat>,
+}
+
+// Повтор уже сохранённой записи seq и её новый счётчик
+#[derive(Debug)]
+pub struct Collapsed { pub seq: u64, pub id: String, pub timestamp: DateTime<Utc>, pub repeat: Repeat }
+
+#[derive(Debug)]
+pub enum Verdict {
+    Keep,
+    // Сэмплирование: запись не сохраняется
+    Drop,
+    Repeat(Collapsed),
+}
+
+#[derive(Debug, Default)]
+struct State {
+    window: Duration,
+    sample_every: HashMap<LogCategory, u32>,
+    seen: HashMap<LogCategory, u64>,
+    hasher: RandomState,
+    // Ключ — хеш всех полей записи, кроме времени: записи с разным источником,
+    // адресом, пользователем или запросом не схлопываются
+    groups: HashMap<u64, Group>,
+    // Окна в порядке открытия: истекают с начала
+    expiry: BTreeMap<u64, (Instant, u64)>,
+    // Окна в порядке последнего повтора: сверх MAX_GROUPS вытесняются с начала
+    recent: BTreeMap<u64, u64>,
+    tick: u64,
+    // Итоги вытесненных окон с повторами, ещё не отданные close()
+    evicted: Vec<(u64, Repeat)>,
+}
+
+impl State {
+    fn key(&self, log: &LogEntry) -> u64 {
+        self.hasher.hash_one((log.category, log.level, &log.message, &log.source, &log.details, &log.ip, &log.user, &log.request_id))
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn remove(&mut self, key: u64) -> Option<Group> {
+        let group = self.groups.remove(&key)?;
+        self.expiry.remove(&group.created);
+        self.recent.remove(&group.used);
+        Some(group)
+    }
+
+    fn finish(&mut self, group: Group) {
+        if let Some(repeat) = group.repeat { self.evicted.push((group.seq, repeat)); }
+    }
+}
+
+// Дедупликация и сэмплирование перед записью в хранилище. Окно считается по
+// времени поступления, first_seen/last_seen — по времени событий.
+#[derive(Debug, Default)]
+pub struct Reducer { state: Mutex<State> }
+
+impl Reducer {
+    pub fn configure(&self, policy: &LogsConfig) {
+        let mut st = self.state.lock().unwrap();
+        st.window = Duration::from_secs(policy.dedup_window_secs);
+        if st.sample_every != policy.sample_every {
+            st.sample_every = policy.sample_every.clone();
+            st.seen.clear();
+        }
+    }
+
+    pub fn check(&self, log: &LogEntry, now: Instant) -> Verdict {
+        let mut st = self.state.lock().unwrap();
+        let window = st.window;
+        let key = st.key(log);
+        let tick = st.tick + 1;
+        if !window.is_zero() && let Some(group) = st.groups.get_mut(&key) && now.duration_since(group.opened) < window {
+            let repeat = group.repeat.get_or_insert(Repeat { count: 1, first_seen: group.timestamp, last_seen: group.timestamp });
+            repeat.count += 1;
+            repeat.first_seen = repeat.first_seen.min(log.timestamp);
+            repeat.last_seen = repeat.last_seen.max(log.timestamp);
+            let collapsed = Collapsed { seq: group.seq, id: group.id.clone(), timestamp: group.timestamp, repeat: repeat.clone() };
+            let used = std::mem::replace(&mut group.used, tick);
+            st.tick = tick;
+            st.recent.remove(&used);
+            st.recent.insert(tick, key);
+            return Verdict::Repeat(collapsed);
+        }
+        if matches!(log.level, LogLevel::Debug | LogLevel::Info) && let Some(every) = st.sample_every.get(&log.category).copied().filter(|n| *n > 1) {
+            let seen = st.seen.entry(log.category).or_default();
+            *seen += 1;
+            if !(*seen - 1).is_multiple_of(every as u64) { return Verdict::Drop; }
+        }
+        Verdict::Keep
+    }
+
+    // Сохранённая запись открывает окно для своих повторов
+    pub fn track(&self, log: &LogEntry, now: Instant) {
+        let mut st = self.state.lock().unwrap();
+        if st.window.is_zero() { return; }
+        let key = st.key(log);
+        // Прежнее окно с тем же ключом уже истекло, но ещё не закрыто
+        if let Some(old) = st.remove(key) { st.finish(old); }
+        let tick = st.next_tick();
+        st.groups.insert(key, Group { seq: log.seq, id: log.id.clone(), timestamp: log.timestamp, opened: now, created: tick, used: tick, repeat: None });
+        st.expiry.insert(tick, (now, key));
+        st.recent.insert(tick, key);
+        if st.groups.len() > MAX_GROUPS && let Some((_, key)) = st.recent.pop_first() && let Some(group) = st.remove(key) {
+            st.finish(group);
+        }
+    }
+
+    // Закрывает истёкшие и вытесненные окна; возвращает итоговые счётчики записей,
+    // у которых были повторы, — их нужно перезаписать на диске
+    pub fn close(&self, now: Instant) -> Vec<(u64, Repeat)> {
+        let mut st = self.state.lock().unwrap();
+        let window = st.window;
+        while st.expiry.first_key_value().is_some_and(|(_, (opened, _))| now.duration_since(*opened) >= window) {
+            let Some((_, (_, key))) = st.expiry.pop_first() else { break };
+            if let Some(group) = st.remove(key) { st.finish(group); }
+        }
+        std::mem::take(&mut st.evicted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reducer(window_secs: u64) -> Reducer {
+        let reducer = Reducer::default();
+        reducer.configure(&LogsConfig { dedup_window_secs: window_secs, ..LogsConfig::default() });
+        reducer
+    }
+
+    fn entry(seq: u64, message: &str) -> LogEntry {
+        LogEntry { seq, id: format!("id-{}", seq), ..LogEntry::new(LogLevel::Error, LogCategory::Database, message, "db.rs:1") }
+    }
+
+    #[test]
+    fn collapses_repeats_within_window() {
+        let reducer = reducer(60);
+        let now = Instant::now();
+        let first = entry(1, "connection refused");
+        assert!(matches!(reducer.check(&first, now), Verdict::Keep));
+        reducer.track(&first, now);
+        match reducer.check(&entry(0, "connection refused"), now + Duration::from_secs(1)) {
+            Verdict::Repeat(c) => { assert_eq!((c.seq, c.id.as_str(), c.repeat.count), (1, "id-1", 2)); }
+            other => panic!("expected repeat, got {:?}", other),
+        }
+        assert!(matches!(reducer.check(&entry(0, "other"), now), Verdict::Keep));
+    }
+
+    #[test]
+    fn entries_from_other_sources_addresses_or_requests_are_kept() {
+        let reducer = reducer(60);
+        let now = Instant::now();
+        let first = LogEntry { ip: Some("10.0.0.1".into()), ..entry(1, "login failed") };
+        reducer.track(&first, now);
+        assert!(matches!(reducer.check(&first, now), Verdict::Repeat(_)));
+        for other in [
+            LogEntry { ip: Some("10.0.0.2".into()), ..first.clone() },
+            LogEntry { user: Some("bob".into()), ..first.clone() },
+            LogEntry { source: "auth.rs:2".into(), ..first.clone() },
+            LogEntry { details: Some("other".into()), ..first.clone() },
+            LogEntry { request_id: Some("req-2".into()), ..first.clone() },
+        ] {
+            assert!(matches!(reducer.check(&other, now), Verdict::Keep), "{:?}", other);
+        }
+    }
+
+    #[test]
+    fn close_reports_final_counts_of_expired_windows() {
+        let reducer = reducer(60);
+        let now = Instant::now();
+        reducer.track(&entry(1, "a"), now);
+        reducer.track(&entry(2, "b"), now + Duration::from_secs(30));
+        reducer.check(&entry(0, "a"), now + Duration::from_secs(10));
+        reducer.check(&entry(0, "a"), now + Duration::from_secs(20));
+        assert!(reducer.close(now + Duration::from_secs(59)).is_empty());
+        let closed = reducer.close(now + Duration::from_secs(60));
+        assert_eq!(closed.len(), 1);
+        assert_eq!((closed[0].0, closed[0].1.count), (1, 3));
+        // Окно "b" ещё открыто, окно "a" закрыто — следующая "a" сохраняется заново
+        assert!(matches!(reducer.check(&entry(0, "b"), now + Duration::from_secs(61)), Verdict::Repeat(_)));
+        assert!(matches!(reducer.check(&entry(0, "a"), now + Duration::from_secs(61)), Verdict::Keep));
+    }
+
+    #[test]
+    fn evicts_least_recently_repeated_group_over_cap() {
+        let reducer = reducer(3600);
+        let now = Instant::now();
+        reducer.track(&entry(1, "first"), now);
+        reducer.track(&entry(2, "second"), now);
+        reducer.check(&entry(0, "first"), now);
+        // "second" не повторялась — вытесняется раньше, чем "first"
+        for seq in 3..=MAX_GROUPS as u64 + 1 { reducer.track(&entry(seq, &format!("unique {}", seq)), now); }
+        assert_eq!(reducer.state.lock().unwrap().groups.len(), MAX_GROUPS);
+        assert!(matches!(reducer.check(&entry(0, "second"), now), Verdict::Keep));
+        assert!(matches!(reducer.check(&entry(0, "first"), now), Verdict::Repeat(_)));
+        reducer.track(&entry(MAX_GROUPS as u64 + 2, "one more"), now);
+        reducer.track(&entry(MAX_GROUPS as u64 + 3, "and another"), now);
+        let st = reducer.state.lock().unwrap();
+        assert_eq!(st.groups.len(), MAX_GROUPS);
+        assert_eq!(st.expiry.len(), MAX_GROUPS);
+        assert_eq!(st.recent.len(), MAX_GROUPS);
+    }
+
+    #[test]
+    fn evicted_repeats_are_reported_before_expiry() {
+        let reducer = reducer(3600);
+        let now = Instant::now();
+        reducer.track(&entry(1, "noisy"), now);
+        reducer.check(&entry(0, "noisy"), now);
+        for seq in 2..=MAX_GROUPS as u64 + 1 { reducer.track(&entry(seq, &format!("unique {}", seq)), now); }
+        let closed = reducer.close(now);
+        assert_eq!(closed.len(), 1);
+        assert_eq!((closed[0].0, closed[0].1.count), (1, 2));
+    }
+}
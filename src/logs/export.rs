@@ -0,0 +1,178 @@
+use std::convert::Infallible;
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use async_stream::stream;
+use chrono::{SecondsFormat, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+use super::{enum_name, LogCategory, LogEntry, LogFilter, LogLevel, LogsStore};
+use super::query::MAX_LIMIT;
+use crate::auth::CurrentUser;
+
+const CSV_HEADER: &str = "id,seq,timestamp,level,category,message,details,ip,user,source,request_id\n";
+// Номер из диапазона для документации (RFC 5612) — у проекта нет своего PEN
+const SD_ID: &str = "dc@32473";
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat { #[default] Ndjson, Csv, Syslog }
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Export {
+    #[serde(default)]
+    pub format: ExportFormat,
+    // Сохранённый поиск вместо параметров фильтра
+    #[serde(default)]
+    pub search: Option<Uuid>,
+}
+
+impl ExportFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Syslog => "text/plain; charset=utf-8",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Syslog => "log",
+        }
+    }
+
+    fn line(self, log: &LogEntry, host: &str) -> String {
+        match self {
+            ExportFormat::Ndjson => {
+                let mut line = serde_json::to_string(log).expect("serializable log entry");
+                line.push('\n');
+                line
+            }
+            ExportFormat::Csv => csv_line(log),
+            ExportFormat::Syslog => syslog_line(log, host),
+        }
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    // Значения, которые табличный редактор принял бы за формулу, экранируются апострофом
+    let value = if value.starts_with(['=', '+', '-', '@']) { format!("'{}", value) } else { value.to_string() };
+    if value.contains([',', '"', '\n', '\r']) { format!("\"{}\"", value.replace('"', "\"\"")) } else { value }
+}
+
+fn csv_line(log: &LogEntry) -> String {
+    let fields = [
+        log.id.clone(),
+        log.seq.to_string(),
+        log.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+        enum_name(log.level),
+        enum_name(log.category),
+        log.message.clone(),
+        log.details.clone().unwrap_or_default(),
+        log.ip.clone().unwrap_or_default(),
+        log.user.clone().unwrap_or_default(),
+        log.source.clone(),
+        log.request_id.clone().unwrap_or_default(),
+    ];
+    let mut line = fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(",");
+    line.push('\n');
+    line
+}
+
+// Уровень журнала -> severity syslog
+fn severity(level: LogLevel) -> u8 {
+    match level {
+        LogLevel::Error => 3,
+        LogLevel::Warning => 4,
+        LogLevel::Success => 5,
+        LogLevel::Info => 6,
+        LogLevel::Debug => 7,
+    }
+}
+
+// Категория -> facility: daemon, authpriv и local0..local3
+fn facility(category: LogCategory) -> u8 {
+    match category {
+        LogCategory::System => 3,
+        LogCategory::Security => 10,
+        LogCategory::Database => 16,
+        LogCategory::Api => 17,
+        LogCategory::User => 18,
+        LogCategory::Network => 19,
+    }
+}
+
+// Поля заголовка RFC 5424: печатный ASCII без пробелов, "-" для пустого значения
+fn header_field(value: &str, max: usize) -> String {
+    let value: String = value.chars().filter(|c| c.is_ascii_graphic()).take(max).collect();
+    if value.is_empty() { "-".into() } else { value }
+}
+
+// Одна запись — одна строка, поэтому переводы строк заменяются пробелами
+fn one_line(value: &str) -> String { value.chars().map(|c| if c.is_control() { ' ' } else { c }).collect() }
+
+fn sd_value(value: &str) -> String {
+    one_line(value).replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]")
+}
+
+fn syslog_line(log: &LogEntry, host: &str) -> String {
+    let mut sd = format!("[{} id=\"{}\" seq=\"{}\" level=\"{}\"", SD_ID, log.id, log.seq, enum_name(log.level));
+    for (name, value) in [("ip", &log.ip), ("user", &log.user), ("request_id", &log.request_id), ("details", &log.details)] {
+        if let Some(value) = value { sd.push_str(&format!(" {}=\"{}\"", name, sd_value(value))); }
+    }
+    sd.push(']');
+    format!(
+        "<{}>1 {} {} {} - {} {} \u{feff}{}\n",
+        facility(log.category) * 8 + severity(log.level),
+        log.timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+        header_field(host, 255),
+        header_field(&log.source, 48),
+        header_field(&enum_name(log.category), 32),
+        sd,
+        one_line(&log.message),
+    )
+}
+
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname").map(|h| h.trim().to_string()).unwrap_or_default()
+}
+
+// Выгрузка журнала с теми же фильтрами, что и список, в хронологическом порядке.
+// Ответ отдаётся потоком порциями, так что большие выгрузки не собираются в памяти целиком,
+// а каждый сегмент на диске читается один раз.
+pub async fn export_logs(State(store): State<LogsStore>, user: Option<CurrentUser>, Query(filter): Query<LogFilter>, Query(export): Query<Export>) -> impl IntoResponse {
+    let filter = match export.search {
+        Some(id) => match store.searches().get(id).await.filter(|s| s.visible_to(user.as_ref().map(|CurrentUser(u)| u))) {
+            Some(search) => search.resolve(Utc::now()),
+            None => return (StatusCode::NOT_FOUND, "Saved search not found").into_response(),
+        },
+        None => filter,
+    };
+    let matcher = match filter.compile() {
+        Ok(m) => m,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let format = export.format;
+    let host = hostname();
+    let body = stream! {
+        if let ExportFormat::Csv = format { yield Ok::<_, Infallible>(CSV_HEADER.to_string()); }
+        let mut logs = store.forward(matcher);
+        while let Some(page) = logs.next().await {
+            for chunk in page.chunks(MAX_LIMIT) { yield Ok(chunk.iter().map(|log| format.line(log, &host)).collect::<String>()); }
+        }
+    };
+    let filename = format!("logs-{}.{}", Utc::now().format("%Y%m%d-%H%M%S"), format.extension());
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        Body::from_stream(body),
+    ).into_response()
+}
@@ -0,0 +1,145 @@
+use std::{collections::HashMap, ops::ControlFlow};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use serde::{Deserialize, Serialize};
+use super::{LogCategory, LogFilter, LogLevel, LogsStore};
+
+// Больше столбцов графику не нужно, а запрос за год по минутам — почти наверняка ошибка
+const MAX_BUCKETS: i64 = 2000;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval { Minute, #[default] Hour, Day }
+
+impl Interval {
+    fn step(self) -> Duration {
+        match self {
+            Interval::Minute => Duration::minutes(1),
+            Interval::Hour => Duration::hours(1),
+            Interval::Day => Duration::days(1),
+        }
+    }
+
+    // Диапазон по умолчанию — 60 минут, 24 часа или 30 дней до текущего момента
+    fn default_span(self) -> Duration {
+        match self {
+            Interval::Minute => Duration::hours(1),
+            Interval::Hour => Duration::days(1),
+            Interval::Day => Duration::days(30),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HistogramParams {
+    #[serde(default)]
+    pub interval: Interval,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Bucket {
+    pub start: DateTime<Utc>,
+    pub total: usize,
+    pub levels: HashMap<LogLevel, usize>,
+    pub categories: HashMap<LogCategory, usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Histogram {
+    pub interval: Interval,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    // Все интервалы диапазона по порядку, включая пустые
+    pub buckets: Vec<Bucket>,
+}
+
+// Гистограмма по уровням и категориям. from/to из фильтра задают диапазон и
+// выравниваются по границам интервала в UTC; остальные параметры фильтра — как у списка.
+pub async fn logs_histogram(State(store): State<LogsStore>, Query(mut filter): Query<LogFilter>, Query(params): Query<HistogramParams>) -> impl IntoResponse {
+    let step = params.interval.step();
+    let to = filter.to.unwrap_or_else(Utc::now);
+    let from = filter.from.unwrap_or(to - params.interval.default_span());
+    let (Ok(from), Ok(to_floor)) = (from.duration_trunc(step), to.duration_trunc(step)) else {
+        return (StatusCode::BAD_REQUEST, "range is out of bounds".to_string()).into_response();
+    };
+    // Неполный последний интервал тоже попадает в ответ
+    let to = if to_floor < to { to_floor + step } else { to_floor };
+    let count = (to - from).num_seconds() / step.num_seconds();
+    if count <= 0 { return (StatusCode::BAD_REQUEST, "from must be earlier than to".to_string()).into_response(); }
+    if count > MAX_BUCKETS {
+        return (StatusCode::BAD_REQUEST, format!("range spans more than {} buckets, use a larger interval", MAX_BUCKETS)).into_response();
+    }
+    filter.from = Some(from);
+    filter.to = Some(to);
+    let matcher = match filter.compile() {
+        Ok(m) => m,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let mut buckets: Vec<Bucket> = (0..count).map(|i| Bucket { start: from + step * i as i32, ..Default::default() }).collect();
+    store.scan(&matcher, None, |log| {
+        let i = ((log.timestamp - from).num_seconds() / step.num_seconds()) as usize;
+        if let Some(bucket) = buckets.get_mut(i) {
+            // Повторы относятся к интервалу первой записи
+            let n = log.occurrences();
+            bucket.total += n;
+            *bucket.levels.entry(log.level).or_default() += n;
+            *bucket.categories.entry(log.category).or_default() += n;
+        }
+        ControlFlow::Continue(())
+    }).await;
+    Json(Histogram { interval: params.interval, from, to, buckets }).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use axum::{body::{to_bytes, Body}, extract::Request, http::Response, routing::get, Router};
+    use chrono::TimeZone;
+    use tower::ServiceExt;
+    use super::*;
+    use crate::{logs::LogEntry, storage::{Db, MemoryStorage}};
+
+    async fn get_histogram(store: &LogsStore, uri: &str) -> Response<Body> {
+        let app = Router::new().route("/logs/histogram", get(logs_histogram)).with_state(store.clone());
+        app.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn range_is_aligned_and_bucket_count_is_limited() {
+        let db: Db = Arc::new(MemoryStorage::default());
+        let store = LogsStore::open_in(&db, None);
+        let at = |h, m| Utc.with_ymd_and_hms(2026, 3, 1, h, m, 0).unwrap();
+        store.append_many([(10, 5, LogLevel::Error), (10, 30, LogLevel::Info), (11, 59, LogLevel::Info), (13, 0, LogLevel::Info)].into_iter().map(|(h, m, level)| {
+            LogEntry { timestamp: at(h, m), ..LogEntry::new(level, LogCategory::Api, "request", "api.rs:1") }
+        }).collect()).await;
+
+        // Границы округляются до часа наружу: 10:10–11:20 превращается в 10:00–12:00
+        let res = get_histogram(&store, "/logs/histogram?interval=hour&from=2026-03-01T10:10:00Z&to=2026-03-01T11:20:00Z").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(res.into_body(), 1 << 20).await.unwrap()).unwrap();
+        assert_eq!(body["from"], "2026-03-01T10:00:00Z");
+        assert_eq!(body["to"], "2026-03-01T12:00:00Z");
+        let buckets = body["buckets"].as_array().unwrap();
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0]["total"], 2);
+        assert_eq!(buckets[0]["levels"]["error"], 1);
+        assert_eq!(buckets[1]["total"], 1);
+
+        // Ровно MAX_BUCKETS минут ещё допустимо, на минуту больше — уже нет
+        let res = get_histogram(&store, "/logs/histogram?interval=minute&from=2026-03-01T00:00:00Z&to=2026-03-02T09:20:00Z").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(res.into_body(), 1 << 20).await.unwrap()).unwrap();
+        assert_eq!(body["buckets"].as_array().unwrap().len(), MAX_BUCKETS as usize);
+        let res = get_histogram(&store, "/logs/histogram?interval=minute&from=2026-03-01T00:00:00Z&to=2026-03-02T09:21:00Z").await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = get_histogram(&store, "/logs/histogram?interval=day&from=2026-03-02T00:00:00Z&to=2026-03-01T00:00:00Z").await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
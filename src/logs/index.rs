@@ -0,0 +1,312 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use super::{LogCategory, LogEntry, LogLevel, Matcher, Repeat};
+
+const LEVELS: [LogLevel; 5] = [LogLevel::Info, LogLevel::Warning, LogLevel::Error, LogLevel::Debug, LogLevel::Success];
+const CATEGORIES: [LogCategory; 6] = [LogCategory::System, LogCategory::Database, LogCategory::Security, LogCategory::Api, LogCategory::User, LogCategory::Network];
+
+// Число событий по сочетаниям уровня и категории (повторы учитываются).
+// Таблица фиксированного размера: сложение агрегатов сегментов не выделяет память.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Counts([[usize; CATEGORIES.len()]; LEVELS.len()]);
+
+impl Counts {
+    fn cell(&mut self, level: LogLevel, category: LogCategory) -> &mut usize {
+        &mut self.0[level as usize][category as usize]
+    }
+
+    pub fn add(&mut self, log: &LogEntry) {
+        *self.cell(log.level, log.category) += log.occurrences();
+    }
+
+    pub fn remove(&mut self, log: &LogEntry) {
+        let n = self.cell(log.level, log.category);
+        *n = n.saturating_sub(log.occurrences());
+    }
+
+    pub fn bump(&mut self, level: LogLevel, category: LogCategory) {
+        *self.cell(level, category) += 1;
+    }
+
+    pub fn merge(&mut self, other: &Counts) {
+        for (row, other) in self.0.iter_mut().zip(&other.0) {
+            for (n, m) in row.iter_mut().zip(other) { *n += m; }
+        }
+    }
+
+    pub fn subtract(&mut self, other: &Counts) {
+        for (row, other) in self.0.iter_mut().zip(&other.0) {
+            for (n, m) in row.iter_mut().zip(other) { *n = n.saturating_sub(*m); }
+        }
+    }
+
+    // Только сочетания, которые пропускает фильтр
+    pub fn matching(&self, matcher: &Matcher) -> Counts {
+        let mut counts = Counts::default();
+        for level in LEVELS {
+            for category in CATEGORIES {
+                if matcher.allows(level, category) { *counts.cell(level, category) = self.0[level as usize][category as usize]; }
+            }
+        }
+        counts
+    }
+
+    pub fn is_empty(&self) -> bool { self.total() == 0 }
+
+    pub fn by_level(&self, level: LogLevel) -> usize { self.0[level as usize].iter().sum() }
+
+    pub fn total(&self) -> usize { self.0.iter().flatten().sum() }
+}
+
+// Номера из нескольких возрастающих списков, слитые от больших к меньшим
+struct MergeDesc<'a> { lists: Vec<(&'a VecDeque<u64>, usize)> }
+
+impl Iterator for MergeDesc<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let (list, end) = self.lists.iter_mut().filter(|(_, end)| *end > 0).max_by_key(|(list, end)| list[*end - 1])?;
+        *end -= 1;
+        Some(list[*end])
+    }
+}
+
+// Записей в блоке индекса времени
+const BLOCK: usize = 256;
+
+fn in_range(ts: DateTime<Utc>, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> bool {
+    from.is_none_or(|from| ts >= from) && to.is_none_or(|to| ts < to)
+}
+
+// Подряд идущие записи окна: границы их времени и счётчики. После вытеснения части
+// записей границы остаются прежними — они лишь шире настоящих, что для отбора безопасно.
+#[derive(Debug, Clone)]
+struct Block { min_ts: DateTime<Utc>, max_ts: DateTime<Utc>, counts: Counts }
+
+impl Block {
+    fn overlaps(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> bool {
+        from.is_none_or(|from| self.max_ts >= from) && to.is_none_or(|to| self.min_ts < to)
+    }
+
+    fn within(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> bool {
+        from.is_none_or(|from| self.min_ts >= from) && to.is_none_or(|to| self.max_ts < to)
+    }
+}
+
+// Окно последних записей в памяти: добавление только в конец, вытеснение с начала.
+// Вторичные индексы по уровню и категории позволяют не просматривать всё окно ради
+// редких записей, блоки — пропускать записи вне диапазона времени, а счётчики —
+// считать статистику без обхода.
+#[derive(Debug, Default)]
+pub struct Index {
+    // От старых к новым, seq возрастает
+    entries: VecDeque<Arc<LogEntry>>,
+    by_level: HashMap<LogLevel, VecDeque<u64>>,
+    by_category: HashMap<LogCategory, VecDeque<u64>>,
+    // Блоки по BLOCK записей; время события не обязано расти вместе с seq, но обычно
+    // почти растёт, так что границы блоков узкие. Блок с номером n покрывает записи
+    // с абсолютными позициями [n * BLOCK, (n + 1) * BLOCK), base — позиция entries[0].
+    blocks: VecDeque<Block>,
+    base: usize,
+    counts: Counts,
+}
+
+impl Index {
+    pub fn oldest_seq(&self) -> Option<u64> { self.entries.front().map(|l| l.seq) }
+
+    pub fn newest_seq(&self) -> Option<u64> { self.entries.back().map(|l| l.seq) }
+
+    // seq записи должен быть больше всех уже добавленных
+    pub fn push(&mut self, log: LogEntry) {
+        debug_assert!(self.newest_seq().is_none_or(|last| log.seq > last));
+        self.by_level.entry(log.level).or_default().push_back(log.seq);
+        self.by_category.entry(log.category).or_default().push_back(log.seq);
+        if (self.base + self.entries.len()).is_multiple_of(BLOCK) {
+            self.blocks.push_back(Block { min_ts: log.timestamp, max_ts: log.timestamp, counts: Counts::default() });
+        }
+        let block = self.blocks.back_mut().expect("block for the new entry");
+        block.min_ts = block.min_ts.min(log.timestamp);
+        block.max_ts = block.max_ts.max(log.timestamp);
+        block.counts.add(&log);
+        self.counts.add(&log);
+        self.entries.push_back(Arc::new(log));
+    }
+
+    // Номер в blocks блока записи entries[i]
+    fn block_of(&self, i: usize) -> usize { (self.base + i) / BLOCK - self.base / BLOCK }
+
+    // Позиции в entries записей блока blocks[b], не дальше end
+    fn block_range(&self, b: usize, end: usize) -> std::ops::Range<usize> {
+        let first = (self.base / BLOCK + b) * BLOCK;
+        let stop = (first + BLOCK - self.base).min(end);
+        first.saturating_sub(self.base).min(stop)..stop
+    }
+
+    fn pop_oldest(&mut self) {
+        let Some(log) = self.entries.pop_front() else { return };
+        // Самая старая запись окна — самая старая и в своих списках уровня и категории
+        if let Some(list) = self.by_level.get_mut(&log.level) { list.pop_front(); }
+        if let Some(list) = self.by_category.get_mut(&log.category) { list.pop_front(); }
+        if let Some(block) = self.blocks.front_mut() { block.counts.remove(&log); }
+        self.base += 1;
+        if self.base.is_multiple_of(BLOCK) { self.blocks.pop_front(); }
+        self.counts.remove(&log);
+    }
+
+    // Оставляет не больше max записей, но не вытесняет записи новее `persisted`
+    pub fn truncate(&mut self, max: usize, persisted: u64) {
+        while self.entries.len() > max && self.oldest_seq().is_some_and(|seq| seq <= persisted) { self.pop_oldest(); }
+    }
+
+    // Удаление из середины (сроки хранения по категориям) перестраивает индексы
+    pub fn retain(&mut self, keep: impl Fn(&LogEntry) -> bool) {
+        let before = self.entries.len();
+        let entries = std::mem::take(&mut self.entries);
+        let kept: Vec<Arc<LogEntry>> = entries.into_iter().filter(|l| keep(l)).collect();
+        if kept.len() == before { self.entries = kept.into(); return; }
+        *self = Self::default();
+        for log in kept { self.push(Arc::unwrap_or_clone(log)); }
+    }
+
+    fn position(&self, seq: u64) -> Option<usize> {
+        // Номера в окне обычно идут подряд; пропуски бывают только после удаления из середины
+        let guess = seq.checked_sub(self.oldest_seq()?).map(|d| d as usize).filter(|i| *i < self.entries.len());
+        if let Some(i) = guess && self.entries[i].seq == seq { return Some(i); }
+        self.entries.binary_search_by_key(&seq, |l| l.seq).ok()
+    }
+
+    pub fn get(&self, seq: u64) -> Option<&Arc<LogEntry>> {
+        self.position(seq).map(|i| &self.entries[i])
+    }
+
+    // Счётчик повторов записи, если она ещё в окне; возвращает обновлённую запись
+    pub fn set_repeat(&mut self, seq: u64, repeat: Option<Repeat>) -> Option<LogEntry> {
+        let i = self.position(seq)?;
+        let b = self.block_of(i);
+        self.counts.remove(&self.entries[i]);
+        self.blocks[b].counts.remove(&self.entries[i]);
+        Arc::make_mut(&mut self.entries[i]).repeat = repeat;
+        self.counts.add(&self.entries[i]);
+        self.blocks[b].counts.add(&self.entries[i]);
+        Some(LogEntry::clone(&self.entries[i]))
+    }
+
+    // Записи с seq < before от новых к старым. Перебираются только кандидаты из самого
+    // узкого индекса; остальные условия фильтра проверяет вызывающий.
+    pub fn newest_first<'a>(&'a self, matcher: &Matcher, before: u64) -> Box<dyn Iterator<Item = &'a Arc<LogEntry>> + 'a> {
+        let levels = matcher.levels();
+        let categories = matcher.categories();
+        let level_size: Option<usize> = (!levels.is_empty()).then(|| levels.iter().filter_map(|l| self.by_level.get(l)).map(VecDeque::len).sum());
+        let category_size: Option<usize> = (!categories.is_empty()).then(|| categories.iter().filter_map(|c| self.by_category.get(c)).map(VecDeque::len).sum());
+        let lists: Vec<&VecDeque<u64>> = match (level_size, category_size) {
+            (Some(l), Some(c)) if c < l => categories.iter().filter_map(|c| self.by_category.get(c)).collect(),
+            (Some(_), _) => levels.iter().filter_map(|l| self.by_level.get(l)).collect(),
+            (None, Some(_)) => categories.iter().filter_map(|c| self.by_category.get(c)).collect(),
+            (None, None) => {
+                let (from, to) = matcher.range();
+                let end = self.entries.partition_point(|l| l.seq < before);
+                if from.is_none() && to.is_none() { return Box::new(self.entries.range(..end).rev()); }
+                // Только диапазон времени: блоки вне диапазона пропускаются целиком
+                let Some(last) = end.checked_sub(1).map(|i| self.block_of(i)) else { return Box::new(std::iter::empty()) };
+                return Box::new((0..=last).rev()
+                    .filter(move |b| self.blocks[*b].overlaps(from, to))
+                    .flat_map(move |b| self.entries.range(self.block_range(b, end)).rev())
+                    .filter(move |l| in_range(l.timestamp, from, to)));
+            }
+        };
+        let merge = MergeDesc { lists: lists.into_iter().map(|list| (list, list.partition_point(|seq| *seq < before))).collect() };
+        Box::new(merge.filter_map(|seq| self.get(seq)))
+    }
+
+    // Записи с seq > after от старых к новым
+    pub fn after(&self, after: u64) -> impl DoubleEndedIterator<Item = &Arc<LogEntry>> {
+        let start = self.entries.partition_point(|l| l.seq <= after);
+        self.entries.range(start..)
+    }
+
+    // Число событий в записях с seq ≤ last по уровню и категории фильтра (без учёта времени)
+    pub fn count_through(&self, matcher: &Matcher, last: u64) -> Counts {
+        let mut counts = Counts::default();
+        let end = self.entries.partition_point(|l| l.seq <= last);
+        let Some(tail) = end.checked_sub(1).map(|i| self.block_of(i)) else { return counts };
+        for block in self.blocks.iter().take(tail) { counts.merge(&block.counts.matching(matcher)); }
+        for log in self.entries.range(self.block_range(tail, end)).filter(|l| matcher.allows(l.level, l.category)) { counts.add(log); }
+        counts
+    }
+
+    // Число событий для фильтра только по уровню, категории и времени
+    pub fn count(&self, matcher: &Matcher) -> Counts {
+        let (from, to) = matcher.range();
+        if from.is_none() && to.is_none() { return self.counts.matching(matcher); }
+        // Блоки целиком внутри диапазона считаются по счётчикам, граничные — по записям
+        let mut counts = Counts::default();
+        for (b, block) in self.blocks.iter().enumerate().filter(|(_, block)| block.overlaps(from, to)) {
+            if block.within(from, to) { counts.merge(&block.counts.matching(matcher)); continue; }
+            for log in self.entries.range(self.block_range(b, self.entries.len())) {
+                if in_range(log.timestamp, from, to) && matcher.allows(log.level, log.category) { counts.add(log); }
+            }
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+    use super::*;
+    use crate::logs::LogFilter;
+
+    fn at(secs: i64) -> DateTime<Utc> { Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap() + Duration::seconds(secs) }
+
+    fn entry(seq: u64) -> LogEntry {
+        // Каждая седьмая запись приходит с опозданием на пять минут
+        let secs = if seq.is_multiple_of(7) { seq as i64 - 300 } else { seq as i64 };
+        let level = LEVELS[seq as usize % LEVELS.len()];
+        let category = CATEGORIES[seq as usize % CATEGORIES.len()];
+        LogEntry { seq, timestamp: at(secs), ..LogEntry::new(level, category, "m", "test") }
+    }
+
+    fn window() -> Index {
+        let mut index = Index::default();
+        for seq in 1..=2000 { index.push(entry(seq)); }
+        // Вытеснение не по границе блока
+        index.truncate(1500, u64::MAX);
+        index.set_repeat(1600, Some(Repeat { count: 3, first_seen: at(1600), last_seen: at(1610) }));
+        index
+    }
+
+    #[test]
+    fn time_range_lists_and_counts_match_full_scan() {
+        let index = window();
+        let ranges = [(Some(600), Some(900)), (None, Some(1000)), (Some(1900), None), (Some(5000), None), (Some(700), Some(701))];
+        for (from, to) in ranges {
+            let (from, to) = (from.map(at), to.map(at));
+            for level in [None, Some(LogLevel::Error)] {
+                let matcher = LogFilter { from, to, level: level.into_iter().collect(), ..LogFilter::default() }.compile().unwrap();
+                let matching = |l: &&Arc<LogEntry>| in_range(l.timestamp, from, to) && level.is_none_or(|level| l.level == level);
+                for before in [u64::MAX, 1200, 501] {
+                    let expected: Vec<u64> = index.entries.iter().rev().filter(|l| l.seq < before).filter(matching).map(|l| l.seq).collect();
+                    let listed: Vec<u64> = index.newest_first(&matcher, before).filter(|l| matcher.matches(l)).map(|l| l.seq).collect();
+                    assert_eq!(listed, expected, "range {:?}..{:?}, level {:?}, before {}", from, to, level, before);
+                }
+                let expected: usize = index.entries.iter().filter(matching).map(|l| l.occurrences()).sum();
+                assert_eq!(index.count(&matcher).total(), expected, "range {:?}..{:?}, level {:?}", from, to, level);
+            }
+        }
+    }
+
+    #[test]
+    fn blocks_follow_eviction() {
+        let mut index = window();
+        index.truncate(10, u64::MAX);
+        assert_eq!(index.oldest_seq(), Some(1991));
+        assert_eq!(index.blocks.len(), index.block_of(index.entries.len() - 1) + 1);
+        let all = LogFilter { from: Some(at(0)), ..LogFilter::default() }.compile().unwrap();
+        assert_eq!(index.count(&all).total(), 10);
+        assert_eq!(index.newest_first(&all, u64::MAX).count(), 10);
+    }
+}
@@ -0,0 +1,125 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use super::{pipeline::Compiled, LogCategory, LogEntry, LogLevel, LogsStore, MAX_CLOCK_SKEW};
+
+// Ограничения на одну запись; общий размер тела задаётся в main.rs
+pub const MAX_MESSAGE_BYTES: usize = 8 * 1024;
+pub const MAX_DETAILS_BYTES: usize = 64 * 1024;
+pub const MAX_FIELD_BYTES: usize = 256;
+pub const MAX_BATCH: usize = 1000;
+
+// Запись от внешнего сервиса. id и seq назначает сервер; время — время события
+// у отправителя (RFC 3339), а если его нет — время приёма.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogIngest {
+    pub level: LogLevel,
+    pub category: LogCategory,
+    pub message: String,
+    #[serde(default)]
+    pub details: Option<String>,
+    #[serde(default)]
+    pub ip: Option<String>,
+    #[serde(default)]
+    pub user: Option<String>,
+    pub source: String,
+    #[serde(default)]
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+fn check_len(field: &str, value: &str, max: usize) -> Result<(), String> {
+    if value.len() > max { return Err(format!("{} exceeds {} bytes", field, max)); }
+    Ok(())
+}
+
+impl LogIngest {
+    pub fn validate(self) -> Result<LogEntry, String> {
+        let message = self.message.trim();
+        if message.is_empty() { return Err("message must not be empty".into()); }
+        check_len("message", message, MAX_MESSAGE_BYTES)?;
+        let source = self.source.trim();
+        if source.is_empty() { return Err("source must not be empty".into()); }
+        check_len("source", source, MAX_FIELD_BYTES)?;
+        if let Some(details) = &self.details { check_len("details", details, MAX_DETAILS_BYTES)?; }
+        if let Some(user) = &self.user { check_len("user", user, MAX_FIELD_BYTES)?; }
+        let ip = match self.ip.as_deref().map(str::trim) {
+            Some(raw) => Some(raw.parse::<IpAddr>().map_err(|_| format!("ip is not a valid address: {:?}", raw))?.to_string()),
+            None => None,
+        };
+        let now = Utc::now();
+        let timestamp = self.timestamp.unwrap_or(now);
+        if timestamp > now + MAX_CLOCK_SKEW { return Err("timestamp is in the future".into()); }
+
+        let mut entry = LogEntry::new(self.level, self.category, message, source);
+        entry.timestamp = timestamp;
+        entry.details = self.details.filter(|d| !d.is_empty());
+        entry.ip = ip;
+        entry.user = self.user.filter(|u| !u.is_empty());
+        Ok(entry)
+    }
+}
+
+// ?pipeline=name — message разбирается конвейером, извлечённые поля заменяют присланные
+#[derive(Debug, Default, Deserialize)]
+pub struct IngestParams {
+    #[serde(default)]
+    pub pipeline: Option<String>,
+}
+
+impl IngestParams {
+    fn pipeline(&self, store: &LogsStore) -> Result<Option<Arc<Compiled>>, String> {
+        match &self.pipeline {
+            Some(name) => store.pipelines().get(name).map(Some).ok_or_else(|| format!("unknown pipeline: {}", name)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn parse(pipeline: &Option<Arc<Compiled>>, entry: LogEntry) -> Option<LogEntry> {
+    match pipeline {
+        Some(p) => p.run(entry).entry,
+        None => Some(entry),
+    }
+}
+
+// Запись, отброшенная конвейером или сэмплированием, не сохраняется: ответ 204 без тела
+pub async fn push_log(State(store): State<LogsStore>, Query(params): Query<IngestParams>, Json(log): Json<LogIngest>) -> impl IntoResponse {
+    let pipeline = match params.pipeline(&store) {
+        Ok(p) => p,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    match log.validate() {
+        Ok(entry) => match parse(&pipeline, entry) {
+            Some(entry) => match store.append(entry).await {
+                Some(stored) => (StatusCode::CREATED, Json(stored)).into_response(),
+                None => StatusCode::NO_CONTENT.into_response(),
+            },
+            None => StatusCode::NO_CONTENT.into_response(),
+        },
+        Err(e) => (StatusCode::UNPROCESSABLE_ENTITY, e).into_response(),
+    }
+}
+
+// Пакет принимается целиком или отклоняется целиком — отправитель может
+// безопасно повторить его после исправления ошибки. В ответе — только сохранённые записи.
+pub async fn push_logs(State(store): State<LogsStore>, Query(params): Query<IngestParams>, Json(batch): Json<Vec<LogIngest>>) -> impl IntoResponse {
+    let pipeline = match params.pipeline(&store) {
+        Ok(p) => p,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    if batch.is_empty() { return (StatusCode::UNPROCESSABLE_ENTITY, "batch is empty".to_string()).into_response(); }
+    if batch.len() > MAX_BATCH {
+        return (StatusCode::UNPROCESSABLE_ENTITY, format!("batch exceeds {} entries", MAX_BATCH)).into_response();
+    }
+    let mut entries = Vec::with_capacity(batch.len());
+    for (i, log) in batch.into_iter().enumerate() {
+        match log.validate() {
+            Ok(entry) => entries.extend(parse(&pipeline, entry)),
+            Err(e) => return (StatusCode::UNPROCESSABLE_ENTITY, format!("entry {}: {}", i, e)).into_response(),
+        }
+    }
+    (StatusCode::CREATED, Json(store.append_many(entries).await)).into_response()
+}
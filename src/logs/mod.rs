@@ -0,0 +1,700 @@
+use std::{ops::ControlFlow, sync::{atomic::{AtomicU64, AtomicUsize, Ordering}, Arc}, time::Instant};
+use axum::{extract::{Query, State}, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use tokio::sync::{broadcast, mpsc, oneshot, RwLock};
+use uuid::Uuid;
+use crate::{settings::{LogsConfig, SettingsStore}, storage::{Collection, Db}};
+use dedup::{Reducer, Verdict};
+use index::{Counts, Index};
+use segments::{Change, Segments, Writer};
+
+mod capture;
+mod dedup;
+mod export;
+mod histogram;
+mod index;
+mod ingest;
+mod pipeline;
+mod query;
+mod searches;
+mod segments;
+mod stream;
+mod syslog;
+mod tail;
+mod tokens;
+pub use capture::{CaptureLayer, CaptureLevel};
+pub use dedup::Repeat;
+pub use export::export_logs;
+pub use histogram::logs_histogram;
+pub use ingest::{push_log, push_logs};
+pub use pipeline::{create_pipeline, delete_pipeline, list_pipelines, test_pipeline, update_pipeline, Pipelines};
+pub use query::{list_logs, LogFilter, Matcher};
+pub use searches::{create_search, delete_search, get_search, list_searches, update_search, SavedSearch, SavedSearches};
+pub use stream::stream_logs;
+pub use syslog::spawn as spawn_syslog;
+pub use tail::{spawn as spawn_tail, validate as validate_tail_sources, TailSource};
+pub use tokens::{accepts_ingest_token, create_token, delete_token, list_tokens, IngestClient, IngestToken, IngestTokens, TOKEN_PREFIX};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel { Info, Warning, Error, Debug, Success }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogCategory { System, Database, Security, Api, User, Network }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub id: String,
+    // Монотонный номер записи, назначается сервером: порядок и курсоры
+    #[serde(default)]
+    pub seq: u64,
+    #[serde(deserialize_with = "de_timestamp")]
+    pub timestamp: DateTime<Utc>,
+    pub level: LogLevel,
+    pub category: LogCategory,
+    pub message: String,
+    pub details: Option<String>,
+    pub ip: Option<String>,
+    pub user: Option<String>,
+    pub source: String,
+    // X-Request-Id запроса, во время которого появилась запись
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    // Есть, только если за окном дедупликации пришли такие же записи
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<Repeat>,
+}
+
+// Раньше время хранилось строкой "2024-01-15 14:32:15" — такие записи читаются как UTC
+fn de_timestamp<'de, D: Deserializer<'de>>(d: D) -> Result<DateTime<Utc>, D::Error> {
+    let raw = String::deserialize(d)?;
+    DateTime::parse_from_rfc3339(&raw).map(|t| t.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(&raw, "%Y-%m-%d %H:%M:%S").map(|t| t.and_utc()))
+        .map_err(serde::de::Error::custom)
+}
+
+// Допустимое расхождение часов источника "в будущее"
+const MAX_CLOCK_SKEW: chrono::Duration = chrono::Duration::minutes(5);
+
+// Обрезает строку до max байт, не разрезая символ
+fn truncate(value: &str, max: usize) -> &str {
+    let mut end = value.len().min(max);
+    while !value.is_char_boundary(end) { end -= 1; }
+    &value[..end]
+}
+
+// Имя уровня или категории так, как оно записывается в JSON
+fn enum_name(value: impl Serialize) -> String {
+    serde_json::to_value(value).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default()
+}
+
+impl LogCategory {
+    // Категория по имени без учёта регистра
+    fn parse(name: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(name.to_lowercase())).ok()
+    }
+}
+
+impl LogEntry {
+    pub fn new(level: LogLevel, category: LogCategory, message: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            id: String::new(),
+            seq: 0,
+            timestamp: Utc::now(),
+            level,
+            category,
+            message: message.into(),
+            details: None,
+            ip: None,
+            user: None,
+            source: source.into(),
+            request_id: crate::request_id::current(),
+            repeat: None,
+        }
+    }
+
+    // Сколько событий представляет запись с учётом схлопнутых повторов
+    pub fn occurrences(&self) -> usize {
+        self.repeat.as_ref().map_or(1, |r| r.count as usize)
+    }
+}
+
+// Ёмкость канала живого потока; отставшие подписчики дочитывают пропущенное из хранилища
+const LIVE_CAPACITY: usize = 1024;
+const RETENTION_INTERVAL: std::time::Duration = std::time::Duration::from_secs(600);
+const DEDUP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+// Сколько записей окна обход берёт за одно взятие блокировки
+const SCAN_CHUNK: usize = 256;
+
+// Сегмент читается и разбирается в пуле блокирующих задач
+async fn read_segment(file: segments::SegmentFile) -> Vec<LogEntry> {
+    tokio::task::spawn_blocking(move || file.read()).await.expect("log segment read panicked")
+}
+
+// Обход журнала от старых к новым для выгрузки целиком: вытесненное из окна читается
+// с диска по сегменту за шаг (каждый сегмент — один раз), дальше окно порциями
+pub struct Forward { store: LogsStore, matcher: Matcher, after: u64 }
+
+impl Forward {
+    // Следующая порция подходящих записей; None — журнал пройден до конца
+    pub async fn next(&mut self) -> Option<Vec<LogEntry>> {
+        loop {
+            let (page, window_start) = {
+                let data = self.store.inner.read().await;
+                let window_start = data.oldest_seq().unwrap_or(u64::MAX);
+                let page: Vec<Arc<LogEntry>> = if self.after + 1 < window_start { Vec::new() } else {
+                    data.after(self.after).filter(|l| self.matcher.matches(l)).take(SCAN_CHUNK).cloned().collect()
+                };
+                (page, window_start)
+            };
+            if self.after + 1 >= window_start {
+                self.after = page.last()?.seq;
+                return Some(page.iter().map(|l| LogEntry::clone(l)).collect());
+            }
+            let Some(file) = self.store.disk.after(self.after, Some(&self.matcher)).into_iter().find(|f| f.first_seq < window_start) else {
+                self.after = window_start - 1;
+                continue;
+            };
+            let (after, last) = (self.after, file.last_seq.min(window_start - 1));
+            self.after = last;
+            let logs: Vec<LogEntry> = read_segment(file).await.into_iter().filter(|l| l.seq > after && l.seq <= last && self.matcher.matches(l)).collect();
+            if !logs.is_empty() { return Some(logs); }
+        }
+    }
+}
+
+// Последние записи держатся в памяти в индексированном окне, весь журнал — в сегментах на диске
+#[derive(Debug, Clone)]
+pub struct LogsStore {
+    inner: Arc<RwLock<Index>>,
+    disk: Arc<Segments>,
+    // Очередь потока записи сегментов; None — журнал только в памяти
+    writes: Option<mpsc::Sender<Change>>,
+    window: Arc<AtomicUsize>,
+    next_seq: Arc<AtomicU64>,
+    live: broadcast::Sender<LogEntry>,
+    pipelines: Pipelines,
+    searches: SavedSearches,
+    tokens: IngestTokens,
+    reducer: Arc<Reducer>,
+}
+
+impl LogsStore {
+    pub fn open(db: &Db) -> Self {
+        Self::open_in(db, segments::dir_from_env())
+    }
+
+    // Сегменты в каталоге `dir`; None — журнал только в памяти
+    pub fn open_in(db: &Db, dir: Option<std::path::PathBuf>) -> Self {
+        let disk = Arc::new(Segments::open(dir));
+        let mut writer = Writer::new(disk.clone());
+        let window = LogsConfig::default().memory_entries;
+        // От новых к старым, как читаются с диска
+        let mut logs = Vec::new();
+        if disk.is_new() {
+            // Первый запуск или переход с хранения журнала в общей базе: записи переносятся в сегменты
+            let legacy = Collection::new(db, "logs").newest_first();
+            let mut stored = legacy.load_or_seed(|l: &LogEntry| l.id.clone(), Self::mock);
+            // Записи без номера (сохранённые до его появления) нумеруются по порядку хранения
+            let next = stored.iter().map(|l| l.seq).max().unwrap_or(0) + 1;
+            for (seq, log) in (next..).zip(stored.iter_mut().rev().filter(|l| l.seq == 0)) {
+                log.seq = seq;
+            }
+            stored.sort_by_key(|l| std::cmp::Reverse(l.seq));
+            writer.append(&stored.iter().rev().cloned().collect::<Vec<_>>());
+            legacy.replace::<LogEntry>(&[], |l| l.id.clone());
+            logs.extend(stored.into_iter().take(window));
+        } else {
+            for file in disk.before(u64::MAX, None) {
+                logs.extend(file.read().into_iter().rev().take(window - logs.len()));
+                if logs.len() >= window { break; }
+            }
+        }
+        let next = disk.last_seq().max(logs.first().map(|l| l.seq).unwrap_or(0)) + 1;
+        let mut index = Index::default();
+        for log in logs.into_iter().rev() { index.push(log); }
+        let (live, _) = broadcast::channel(LIVE_CAPACITY);
+        Self {
+            inner: Arc::new(RwLock::new(index)),
+            writes: disk.is_persistent().then(|| writer.spawn()),
+            disk,
+            window: Arc::new(AtomicUsize::new(window)),
+            next_seq: Arc::new(AtomicU64::new(next)),
+            live,
+            pipelines: Pipelines::open(db),
+            searches: SavedSearches::open(db),
+            tokens: IngestTokens::open(db),
+            reducer: Arc::new(Reducer::default()),
+        }
+    }
+
+    fn mock() -> Vec<LogEntry> {
+        vec![
+            LogEntry { id: Uuid::new_v4().to_string(), seq: 2, timestamp: Utc.with_ymd_and_hms(2024, 1, 15, 14, 32, 15).unwrap(), level: LogLevel::Error, category: LogCategory::Database, message: "Ошибка подключения к базе данных".into(), details: Some("Connection timeout after 30 seconds. Host: db.example.com:5432".into()), ip: None, user: None, source: "DatabaseConnector.rs:45".into(), request_id: None, repeat: None },
+            LogEntry { id: Uuid::new_v4().to_string(), seq: 1, timestamp: Utc.with_ymd_and_hms(2024, 1, 15, 14, 31, 42).unwrap(), level: LogLevel::Warning, category: LogCategory::Security, message: "Неудачная попытка входа".into(), details: Some("Invalid password for user: admin".into()), ip: Some("192.168.1.100".into()), user: Some("admin".into()), source: "AuthService.rs:120".into(), request_id: None, repeat: None },
+        ]
+    }
+
+    // Запись из самого сервера (аутентификация, фоновые задачи и т.п.).
+    // None — запись отброшена сэмплированием.
+    pub async fn append(&self, log: LogEntry) -> Option<LogEntry> {
+        self.append_many(vec![log]).await.pop()
+    }
+
+    // id и seq всегда назначает сервер; seq выдаётся под блокировкой, чтобы порядок
+    // номеров совпадал с порядком в хранилище и в живом потоке. Повтор недавней записи
+    // не сохраняется отдельно, а увеличивает её счётчик; в ответе — эта запись.
+    // В живой поток уходит каждое событие: новая запись или первая запись с новым счётчиком.
+    // На диск записи пишет отдельный поток; место в его очереди занимается до блокировки окна,
+    // а передаются они под ней — в том же порядке номеров.
+    pub async fn append_many(&self, logs: Vec<LogEntry>) -> Vec<LogEntry> {
+        let permit = match &self.writes {
+            Some(writes) => writes.reserve().await.map_err(|_| self.writer_stopped()).ok(),
+            None => None,
+        };
+        let mut data = self.inner.write().await;
+        let mut repeats = Vec::new();
+        let now = Instant::now();
+        let mut stored: Vec<LogEntry> = Vec::with_capacity(logs.len());
+        let mut result = Vec::with_capacity(logs.len());
+        for mut log in logs {
+            match self.reducer.check(&log, now) {
+                Verdict::Drop => {}
+                Verdict::Repeat(first) => {
+                    // Первая запись ещё в этом же пакете или уже записана; если она вытеснена
+                    // из окна, в ответе — эта запись с номером и счётчиком первой
+                    let entry = if let Ok(i) = stored.binary_search_by_key(&first.seq, |l| l.seq) {
+                        stored[i].repeat = Some(first.repeat);
+                        stored[i].clone()
+                    } else {
+                        repeats.push((first.seq, log.level, log.category));
+                        match data.set_repeat(first.seq, Some(first.repeat.clone())) {
+                            Some(entry) => entry,
+                            None => LogEntry { id: first.id, seq: first.seq, timestamp: first.timestamp, repeat: Some(first.repeat), ..log },
+                        }
+                    };
+                    result.push(entry);
+                }
+                Verdict::Keep => {
+                    log.id = Uuid::new_v4().to_string();
+                    log.seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+                    self.reducer.track(&log, now);
+                    stored.push(log.clone());
+                    result.push(log);
+                }
+            }
+        }
+        for log in &stored { data.push(log.clone()); }
+        if let Some(permit) = permit { permit.send(Change::Append { logs: stored, repeats }); }
+        // Вытесняются только записи, уже попавшие в сегменты
+        data.truncate(self.window.load(Ordering::Relaxed), self.disk.persisted());
+        drop(data);
+        // Правила оповещений считают каждый повтор как отдельное событие
+        for log in &result {
+            // Ошибка означает лишь отсутствие подписчиков
+            let _ = self.live.send(log.clone());
+        }
+        result
+    }
+
+    // Поток записи сегментов упал: новые записи остаются только в окне памяти, а
+    // вызывающие продолжают работать. Сообщается один раз.
+    fn writer_stopped(&self) {
+        if self.disk.stop() { tracing::error!("Log writer stopped; new log entries are kept in memory only"); }
+    }
+
+    // Конвейеры разбора сырых строк для всех источников записей
+    pub fn pipelines(&self) -> &Pipelines { &self.pipelines }
+
+    pub fn searches(&self) -> &SavedSearches { &self.searches }
+
+    pub fn tokens(&self) -> &IngestTokens { &self.tokens }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LogEntry> { self.live.subscribe() }
+
+    pub async fn last_seq(&self) -> u64 {
+        self.inner.read().await.newest_seq().unwrap_or(0)
+    }
+
+    // Все подходящие записи от новых к старым: сначала окно в памяти, затем диск.
+    // Обход прекращается, когда `visit` возвращает Break. Окно читается порциями, а
+    // сегменты — без блокировок и вне рабочих потоков, так что долгий поиск не задерживает запись.
+    pub async fn scan(&self, matcher: &Matcher, cursor: Option<u64>, mut visit: impl FnMut(LogEntry) -> ControlFlow<()>) {
+        let mut before = cursor.unwrap_or(u64::MAX);
+        let window_start = loop {
+            let (chunk, oldest) = {
+                let data = self.inner.read().await;
+                let chunk: Vec<Arc<LogEntry>> = data.newest_first(matcher, before).filter(|l| matcher.matches(l)).take(SCAN_CHUNK).cloned().collect();
+                (chunk, data.oldest_seq())
+            };
+            for log in &chunk {
+                if visit(LogEntry::clone(log)).is_break() { return; }
+            }
+            match chunk.last() {
+                Some(last) if chunk.len() == SCAN_CHUNK => before = last.seq,
+                _ => break oldest,
+            }
+        };
+        // Окно содержит самые новые записи, так что на диске ищем только то, что старше него
+        let before = window_start.map_or(before, |seq| seq.min(before));
+        for file in self.disk.before(before, Some(matcher)) {
+            for log in read_segment(file).await.into_iter().rev().filter(|l| l.seq < before && matcher.matches(l)) {
+                if visit(log).is_break() { return; }
+            }
+        }
+    }
+
+    // Самая новая подходящая запись в окне памяти; диск не читается
+    pub async fn newest_in_window(&self, matcher: &Matcher) -> Option<LogEntry> {
+        let mut before = u64::MAX;
+        loop {
+            let data = self.inner.read().await;
+            let mut last = None;
+            for log in data.newest_first(matcher, before).take(SCAN_CHUNK) {
+                if matcher.matches(log) { return Some(LogEntry::clone(log)); }
+                last = Some(log.seq);
+            }
+            before = last?;
+        }
+    }
+
+    // Страница подходящих записей от новых к старым, начиная с записей старше `cursor`.
+    // Второе значение — курсор следующей страницы, если она есть.
+    pub async fn query(&self, matcher: &Matcher, cursor: Option<u64>, limit: usize) -> (Vec<LogEntry>, Option<u64>) {
+        let mut items = Vec::new();
+        self.scan(matcher, cursor, |log| {
+            items.push(log);
+            if items.len() > limit { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        }).await;
+        if items.len() <= limit { return (items, None); }
+        items.truncate(limit);
+        let next = items.last().map(|l| l.seq);
+        (items, next)
+    }
+
+    // Фильтр по уровню, категории и времени считается по счётчикам окна и сегментов
+    // без чтения записей (читаются только сегменты на границах диапазона); остальные — обходом
+    pub async fn stats(&self, matcher: &Matcher) -> Stats {
+        if !matcher.is_simple() {
+            let mut counts = Counts::default();
+            self.scan(matcher, None, |log| { counts.add(&log); ControlFlow::Continue(()) }).await;
+            return counts.into();
+        }
+        let (mut counts, partial, window_start) = {
+            let data = self.inner.read().await;
+            let mut counts = data.count(matcher);
+            // На диске считаются только записи старше окна
+            let window_start = data.oldest_seq().unwrap_or(u64::MAX);
+            let Some(tally) = self.disk.count(matcher, window_start) else { return counts.into() };
+            counts.merge(&tally.counts);
+            // Сегмент целиком внутри диапазона, значит и его записи в окне тоже
+            if let Some(last) = tally.overlap { counts.subtract(&data.count_through(matcher, last)); }
+            (counts, tally.partial, window_start)
+        };
+        for file in partial {
+            for log in read_segment(file).await.iter().filter(|l| l.seq < window_start && matcher.matches(l)) { counts.add(log); }
+        }
+        counts.into()
+    }
+
+    // Курсор по всем подходящим записям от старых к новым
+    pub fn forward(&self, matcher: Matcher) -> Forward {
+        Forward { store: self.clone(), matcher, after: 0 }
+    }
+
+    // Подходящие записи новее `after`, от старых к новым — для досылки пропущенного в поток
+    pub async fn since(&self, matcher: &Matcher, after: u64, limit: usize) -> Vec<LogEntry> {
+        let (window, window_start) = {
+            let data = self.inner.read().await;
+            let window: Vec<Arc<LogEntry>> = data.after(after).filter(|l| matcher.matches(l)).take(limit).cloned().collect();
+            (window, data.oldest_seq().unwrap_or(u64::MAX))
+        };
+        let mut items = Vec::new();
+        // Часть, уже вытесненная из окна, читается с диска
+        if after + 1 < window_start {
+            'disk: for file in self.disk.after(after, Some(matcher)) {
+                if file.first_seq >= window_start { break; }
+                for log in read_segment(file).await.into_iter().filter(|l| l.seq > after) {
+                    if log.seq >= window_start { break 'disk; }
+                    if !matcher.matches(&log) { continue; }
+                    items.push(log);
+                    if items.len() >= limit { break 'disk; }
+                }
+            }
+        }
+        items.extend(window.iter().take(limit - items.len()).map(|l| LogEntry::clone(l)));
+        items
+    }
+
+    // Применяет политику хранения к сегментам и окну в памяти
+    pub async fn apply_retention(&self, policy: &LogsConfig) {
+        self.window.store(policy.memory_entries.max(1), Ordering::Relaxed);
+        let now = Utc::now();
+        let report = match &self.writes {
+            Some(writes) => {
+                let (reply, report) = oneshot::channel();
+                let _ = writes.send(Change::Enforce(Box::new(policy.clone()), now, reply)).await;
+                report.await.unwrap_or_else(|_| { self.writer_stopped(); segments::Retained::default() })
+            }
+            None => segments::Retained::default(),
+        };
+        let mut data = self.inner.write().await;
+        data.retain(|l| !segments::expired(policy, l, now) && report.oldest_seq.is_none_or(|oldest| l.seq >= oldest));
+        data.truncate(policy.memory_entries.max(1), self.disk.persisted());
+        if report.dropped > 0 || report.deleted > 0 {
+            tracing::info!(dropped = report.dropped, deleted = report.deleted, compacted = report.compacted, "Log retention applied");
+        }
+    }
+
+    // Фоновая задача: настройки дедупликации и сэмплирования берутся из настроек, а для записей,
+    // окно повторов которых закрылось, на диск дописывается итоговый счётчик
+    pub fn spawn_dedup(&self, settings: SettingsStore) {
+        let store = self.clone();
+        let mut changes = settings.subscribe();
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(DEDUP_INTERVAL);
+            loop {
+                tokio::select! {
+                    changed = changes.changed() => { if changed.is_err() { break; } }
+                    _ = tick.tick() => {}
+                }
+                store.reducer.configure(&changes.borrow_and_update().logs);
+                let closed = store.reducer.close(Instant::now());
+                if closed.is_empty() { continue; }
+                if let Some(writes) = &store.writes && writes.send(Change::Amend(closed)).await.is_err() { store.writer_stopped(); }
+            }
+        });
+    }
+
+    // Фоновая задача: политика применяется периодически и сразу после сохранения настроек
+    pub fn spawn_retention(&self, settings: SettingsStore) {
+        let store = self.clone();
+        let mut changes = settings.subscribe();
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(RETENTION_INTERVAL);
+            loop {
+                tokio::select! {
+                    changed = changes.changed() => { if changed.is_err() { break; } }
+                    _ = tick.tick() => {}
+                }
+                let policy = changes.borrow_and_update().logs.clone();
+                store.apply_retention(&policy).await;
+            }
+        });
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stats { pub total: usize, pub errors: usize, pub warnings: usize, pub info: usize, pub debug: usize, pub success: usize }
+
+impl From<Counts> for Stats {
+    fn from(counts: Counts) -> Self {
+        Self {
+            total: counts.total(),
+            errors: counts.by_level(LogLevel::Error),
+            warnings: counts.by_level(LogLevel::Warning),
+            info: counts.by_level(LogLevel::Info),
+            debug: counts.by_level(LogLevel::Debug),
+            success: counts.by_level(LogLevel::Success),
+        }
+    }
+}
+
+pub async fn logs_stats(State(store): State<LogsStore>, Query(filter): Query<LogFilter>) -> impl IntoResponse {
+    let matcher = match filter.compile() {
+        Ok(m) => m,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    Json(store.stats(&matcher).await).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    // Временный каталог сегментов, удаляемый после теста
+    pub(super) struct TempDir(pub PathBuf);
+
+    impl TempDir {
+        pub fn new() -> Self { Self(std::env::temp_dir().join(format!("dc_logs_{}", Uuid::new_v4()))) }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) { let _ = std::fs::remove_dir_all(&self.0); }
+    }
+
+    pub(super) async fn store(dir: &TempDir, memory_entries: usize) -> LogsStore {
+        let db: Db = Arc::new(MemoryStorage::default());
+        let store = LogsStore::open_in(&db, Some(dir.0.clone()));
+        store.apply_retention(&LogsConfig { memory_entries, max_age_days: 0, ..LogsConfig::default() }).await;
+        store
+    }
+
+    fn entry(i: usize) -> LogEntry {
+        let level = if i.is_multiple_of(10) { LogLevel::Error } else { LogLevel::Info };
+        LogEntry::new(level, LogCategory::Api, format!("request {}", i), "test")
+    }
+
+    #[tokio::test]
+    async fn queries_reach_entries_past_the_window() {
+        let dir = TempDir::new();
+        let store = store(&dir, 10).await;
+        // Затравочные записи из пустой базы
+        let seeded = store.last_seq().await as usize;
+        for chunk in (0..600).collect::<Vec<_>>().chunks(100) {
+            store.append_many(chunk.iter().copied().map(entry).collect()).await;
+        }
+        let api = LogFilter { category: vec![LogCategory::Api], ..LogFilter::default() }.compile().unwrap();
+        let (page, next) = store.query(&api, None, 1000).await;
+        assert_eq!(page.len(), 600);
+        assert!(next.is_none());
+        assert!(page.windows(2).all(|w| w[0].seq > w[1].seq));
+
+        let errors = LogFilter { level: vec![LogLevel::Error], category: vec![LogCategory::Api], ..LogFilter::default() }.compile().unwrap();
+        let (page, next) = store.query(&errors, None, 25).await;
+        assert_eq!(page.len(), 25);
+        let (rest, _) = store.query(&errors, next, 1000).await;
+        assert_eq!(rest.len(), 35);
+        assert!(rest.iter().all(|l| l.level == LogLevel::Error && l.seq < next.unwrap()));
+
+        let replay = store.since(&api, seeded as u64, 1000).await;
+        assert_eq!(replay.len(), 600);
+        assert!(replay.windows(2).all(|w| w[0].seq < w[1].seq));
+        assert_eq!(store.since(&api, seeded as u64 + 590, 1000).await.len(), 10);
+
+        let stats = store.stats(&errors).await;
+        assert_eq!((stats.total, stats.errors), (60, 60));
+        assert_eq!(store.stats(&api).await.total, 600);
+        let now = Utc::now();
+        let wide = LogFilter { from: Some(now - chrono::Duration::hours(1)), to: Some(now + chrono::Duration::hours(1)), ..LogFilter::default() }.compile().unwrap();
+        assert_eq!(store.stats(&wide).await.total, 600);
+        let narrow = LogFilter { from: Some(now - chrono::Duration::hours(1)), to: Some(page[0].timestamp), ..LogFilter::default() }.compile().unwrap();
+        let expected = store.query(&narrow, None, 1000).await.0.len();
+        assert_eq!(store.stats(&narrow).await.total, expected);
+    }
+
+    #[tokio::test]
+    async fn retention_after_restart_keeps_newest_segment_and_numbering() {
+        let dir = TempDir::new();
+        let last = {
+            let store = store(&dir, 10).await;
+            let old = chrono::Duration::days(10);
+            store.append_many((0..20).map(|i| LogEntry { timestamp: Utc::now() - old, ..entry(i) }).collect()).await;
+            store.apply_retention(&LogsConfig { memory_entries: 10, max_age_days: 0, ..LogsConfig::default() }).await;
+            store.last_seq().await
+        };
+        // После перезапуска сегмент закрыт, но остаётся единственным — его нельзя удалять
+        let restarted = store(&dir, 10).await;
+        restarted.apply_retention(&LogsConfig { memory_entries: 10, max_age_days: 1, ..LogsConfig::default() }).await;
+        assert_eq!(restarted.disk.after(0, None).len(), 1);
+        assert_eq!(restarted.disk.last_seq(), last);
+        drop(restarted);
+
+        // Даже без сегментов нумерация продолжается с сохранённого номера
+        for file in std::fs::read_dir(&dir.0).unwrap().filter_map(Result::ok).filter(|f| f.path().extension().is_some_and(|e| e == "log")) {
+            std::fs::remove_file(file.path()).unwrap();
+        }
+        let store = store(&dir, 10).await;
+        assert_eq!(store.append(entry(0)).await.unwrap().seq, last + 1);
+    }
+
+    #[tokio::test]
+    async fn closed_repeats_are_appended_as_amendments() {
+        let dir = TempDir::new();
+        let seq = {
+            let store = store(&dir, 10).await;
+            store.reducer.configure(&LogsConfig { dedup_window_secs: 60, ..LogsConfig::default() });
+            let seq = store.append(entry(1)).await.unwrap().seq;
+            store.append_many(vec![entry(2), entry(1), entry(1)]).await;
+            let closed = store.reducer.close(Instant::now() + std::time::Duration::from_secs(60));
+            store.writes.as_ref().unwrap().send(Change::Amend(closed)).await.unwrap();
+            // Дожидается потока записи
+            store.apply_retention(&LogsConfig { memory_entries: 10, max_age_days: 0, ..LogsConfig::default() }).await;
+            seq
+        };
+        let file = std::fs::read_dir(&dir.0).unwrap().filter_map(Result::ok).find(|f| f.path().extension().is_some_and(|e| e == "log")).unwrap();
+        let body = std::fs::read_to_string(file.path()).unwrap();
+        assert!(body.lines().last().unwrap().starts_with("{\"amend\":"));
+        // Сама запись не переписана: поправка применяется при чтении
+        assert!(body.lines().filter(|l| l.contains("\"request 1\"")).all(|l| !l.contains("\"repeat\"")));
+
+        let restarted = store(&dir, 10).await;
+        let all = LogFilter::default().compile().unwrap();
+        let (page, _) = restarted.query(&all, None, 100).await;
+        let first = page.iter().find(|l| l.seq == seq).unwrap();
+        assert_eq!(first.repeat.as_ref().map(|r| r.count), Some(3));
+        assert_eq!(restarted.stats(&all).await.total, page.iter().map(LogEntry::occurrences).sum::<usize>());
+    }
+
+    #[tokio::test]
+    async fn sealed_segments_load_from_their_description() {
+        let dir = TempDir::new();
+        let policy = LogsConfig { memory_entries: 10, max_age_days: 0, ..LogsConfig::default() };
+        let files = |ext: &str| -> Vec<PathBuf> {
+            std::fs::read_dir(&dir.0).unwrap().filter_map(Result::ok).map(|f| f.path()).filter(|p| p.extension().is_some_and(|e| e == ext)).collect()
+        };
+        for batch in [0..20, 20..21] {
+            // После перезапуска запись идёт в новый сегмент, а прежний закрывается с описанием
+            let store = store(&dir, 10).await;
+            store.append_many(batch.map(entry).collect()).await;
+            store.apply_retention(&policy).await;
+        }
+        let metas = files("meta");
+        assert_eq!((files("log").len(), metas.len()), (2, 1));
+
+        let recent = LogFilter { from: Some(Utc::now() - chrono::Duration::hours(1)), ..LogFilter::default() }.compile().unwrap();
+        let found = |store: LogsStore| { let recent = recent.clone(); async move { store.query(&recent, None, 100).await.0.len() } };
+        assert_eq!(found(store(&dir, 10).await).await, 21);
+        // Сегмент не разбирается при запуске: диапазон времени берётся из описания
+        let mut meta: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&metas[0]).unwrap()).unwrap();
+        meta["max_ts"] = "2000-01-01T00:00:00Z".into();
+        std::fs::write(&metas[0], meta.to_string()).unwrap();
+        assert_eq!(found(store(&dir, 10).await).await, 10);
+        // Файл изменился после описания — оно не используется
+        let sealed = metas[0].with_extension("log");
+        let mut body = std::fs::read_to_string(&sealed).unwrap();
+        body.push_str("{\"partial\n");
+        std::fs::write(&sealed, body).unwrap();
+        assert_eq!(found(store(&dir, 10).await).await, 21);
+    }
+
+    #[tokio::test]
+    async fn stopped_writer_keeps_entries_in_memory() {
+        let dir = TempDir::new();
+        let mut store = store(&dir, 10).await;
+        let (writes, stopped) = mpsc::channel(1);
+        drop(stopped);
+        store.writes = Some(writes);
+        store.append_many((0..30).map(entry).collect()).await;
+        store.apply_retention(&LogsConfig { memory_entries: 10, max_age_days: 0, ..LogsConfig::default() }).await;
+        let all = LogFilter::default().compile().unwrap();
+        assert_eq!(store.inner.read().await.count(&all).total(), 10);
+        assert_eq!(store.append(entry(30)).await.map(|l| l.message), Some("request 30".into()));
+    }
+
+    #[tokio::test]
+    async fn forward_visits_every_entry_once_across_segments() {
+        let dir = TempDir::new();
+        let store = store(&dir, 100).await;
+        let policy = LogsConfig { memory_entries: 100, segment_size_mb: 1, max_age_days: 0, ..LogsConfig::default() };
+        store.apply_retention(&policy).await;
+        // ~3,5 МБ при минимальном размере сегмента в 1 МБ
+        let padding = "x".repeat(1000);
+        for chunk in (0..3500).collect::<Vec<_>>().chunks(500) {
+            store.append_many(chunk.iter().map(|&i| LogEntry { details: Some(padding.clone()), ..entry(i) }).collect()).await;
+        }
+        store.apply_retention(&policy).await;
+        assert!(store.disk.after(0, None).len() > 1);
+
+        let errors = LogFilter { level: vec![LogLevel::Error], category: vec![LogCategory::Api], ..LogFilter::default() }.compile().unwrap();
+        let mut logs = store.forward(errors);
+        let mut seen = Vec::new();
+        while let Some(page) = logs.next().await { seen.extend(page.into_iter().map(|l| l.message)); }
+        let expected: Vec<String> = (0..3500).filter(|i| i % 10 == 0).map(|i| format!("request {}", i)).collect();
+        assert_eq!(seen, expected);
+    }
+}
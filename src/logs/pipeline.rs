@@ -0,0 +1,401 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::IpAddr,
+    sync::{Arc, RwLock},
+};
+use axum::{extract::{Path, State}, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use super::{enum_name, ingest::{MAX_DETAILS_BYTES, MAX_FIELD_BYTES, MAX_MESSAGE_BYTES}, truncate, LogCategory, LogEntry, LogLevel, LogsStore, MAX_CLOCK_SKEW};
+use crate::storage::{Collection, Db};
+
+const MAX_NAME: usize = 64;
+const MAX_TEST_LINES: usize = 100;
+
+// Встроенные шаблоны для %{NAME} и %{NAME:field}
+const GROK: &[(&str, &str)] = &[
+    ("WORD", r"\b\w+\b"),
+    ("NOTSPACE", r"\S+"),
+    ("SPACE", r"\s*"),
+    ("DATA", r".*?"),
+    ("GREEDYDATA", r".*"),
+    ("INT", r"[+-]?\d+"),
+    ("POSINT", r"\d+"),
+    ("NUMBER", r"[+-]?(?:\d+(?:\.\d*)?|\.\d+)"),
+    ("IPV4", r"(?:\d{1,3}\.){3}\d{1,3}"),
+    ("IPV6", r"[0-9A-Fa-f]*:[0-9A-Fa-f:.]+"),
+    ("IP", r"(?:(?:\d{1,3}\.){3}\d{1,3}|[0-9A-Fa-f]*:[0-9A-Fa-f:.]+)"),
+    ("HOSTNAME", r"[0-9A-Za-z][0-9A-Za-z\-.]*"),
+    ("USERNAME", r"[a-zA-Z0-9._-]+"),
+    ("LOGLEVEL", r"(?i:trace|debug|info|notice|warn(?:ing)?|err(?:or)?|crit(?:ical)?|fatal|panic|success)"),
+    ("TIMESTAMP_ISO8601", r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:Z|[+-]\d{2}:?\d{2})?"),
+    ("HTTPDATE", r"\d{2}/\w{3}/\d{4}:\d{2}:\d{2}:\d{2} [+-]\d{4}"),
+    ("SYSLOGTIMESTAMP", r"\w{3} [ \d]\d \d{2}:\d{2}:\d{2}"),
+    ("QS", r#""(?:[^"\\]|\\.)*""#),
+    ("UUID", r"[0-9A-Fa-f]{8}-(?:[0-9A-Fa-f]{4}-){3}[0-9A-Fa-f]{12}"),
+    ("URIPATHPARAM", r"/[^\s]*"),
+];
+
+fn default_field() -> String { "message".into() }
+
+// Запись отбрасывается, если поле (извлечённое или стандартное) подходит под выражение
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DropRule {
+    #[serde(default = "default_field")]
+    pub field: String,
+    pub pattern: String,
+}
+
+// Именованный конвейер разбора: первое подошедшее выражение извлекает поля.
+// Поля message, level, category, source, ip, user, details и timestamp попадают
+// в одноимённые поля записи, остальные дописываются в details как name=value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pipeline {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    // Регулярные выражения с именованными группами и/или шаблоны %{NAME:field}
+    pub patterns: Vec<String>,
+    // Извлечённое имя -> имя поля записи
+    #[serde(default)]
+    pub rename: HashMap<String, String>,
+    // Значение поля level (без учёта регистра) -> уровень; без совпадения — стандартные имена уровней
+    #[serde(default)]
+    pub levels: HashMap<String, LogLevel>,
+    #[serde(default)]
+    pub drop: Vec<DropRule>,
+    // Строки, не подошедшие ни под одно выражение, отбрасываются, а не проходят как есть
+    #[serde(default)]
+    pub drop_unmatched: bool,
+}
+
+// Заменяет %{NAME} и %{NAME:field} на выражения из библиотеки
+fn expand_grok(pattern: &str) -> Result<String, String> {
+    let reference = Regex::new(r"%\{(\w+)(?::(\w+))?\}").expect("grok reference pattern is valid");
+    let mut out = String::with_capacity(pattern.len());
+    let mut last = 0;
+    for caps in reference.captures_iter(pattern) {
+        let whole = caps.get(0).expect("match has group 0");
+        let name = &caps[1];
+        let Some((_, re)) = GROK.iter().find(|(n, _)| *n == name) else { return Err(format!("unknown grok pattern %{{{}}}", name)) };
+        out.push_str(&pattern[last..whole.start()]);
+        match caps.get(2) {
+            Some(field) => out.push_str(&format!("(?P<{}>{})", field.as_str(), re)),
+            None => out.push_str(&format!("(?:{})", re)),
+        }
+        last = whole.end();
+    }
+    out.push_str(&pattern[last..]);
+    Ok(out)
+}
+
+fn default_level(value: &str) -> Option<LogLevel> {
+    match value {
+        "error" | "err" | "fatal" | "panic" | "crit" | "critical" | "alert" | "emerg" => Some(LogLevel::Error),
+        "warn" | "warning" => Some(LogLevel::Warning),
+        "info" | "notice" | "information" => Some(LogLevel::Info),
+        "debug" | "trace" => Some(LogLevel::Debug),
+        "success" | "ok" => Some(LogLevel::Success),
+        _ => None,
+    }
+}
+
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value).ok()
+        .or_else(|| DateTime::parse_from_str(value, "%d/%b/%Y:%H:%M:%S %z").ok())
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f").ok().map(|t| t.and_utc()))
+        .or_else(|| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f").ok().map(|t| t.and_utc()))
+}
+
+fn standard_field(entry: &LogEntry, field: &str) -> Option<String> {
+    match field {
+        "message" => Some(entry.message.clone()),
+        "level" => Some(enum_name(entry.level)),
+        "category" => Some(enum_name(entry.category)),
+        "source" => Some(entry.source.clone()),
+        "ip" => entry.ip.clone(),
+        "user" => entry.user.clone(),
+        "details" => entry.details.clone(),
+        _ => None,
+    }
+}
+
+// Результат разбора одной строки
+#[derive(Debug, Serialize)]
+pub struct Parsed {
+    pub matched: bool,
+    // Поля после переименования
+    pub fields: BTreeMap<String, String>,
+    // None — запись отброшена
+    pub entry: Option<LogEntry>,
+}
+
+pub struct Compiled {
+    pipeline: Pipeline,
+    patterns: Vec<Regex>,
+    drop: Vec<(String, Regex)>,
+}
+
+impl Compiled {
+    pub fn new(pipeline: Pipeline) -> Result<Self, String> {
+        let name = pipeline.name.trim();
+        if name.is_empty() { return Err("name must not be empty".into()); }
+        if name.len() > MAX_NAME || !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+            return Err(format!("name must be at most {} characters of [A-Za-z0-9-_.]", MAX_NAME));
+        }
+        if pipeline.patterns.is_empty() { return Err("patterns must not be empty".into()); }
+        let patterns = pipeline.patterns.iter().enumerate()
+            .map(|(i, p)| expand_grok(p).and_then(|re| Regex::new(&re).map_err(|e| e.to_string())).map_err(|e| format!("patterns[{}]: {}", i, e)))
+            .collect::<Result<_, _>>()?;
+        let drop = pipeline.drop.iter().enumerate()
+            .map(|(i, r)| Regex::new(&r.pattern).map(|re| (r.field.clone(), re)).map_err(|e| format!("drop[{}]: {}", i, e)))
+            .collect::<Result<_, _>>()?;
+        let mut pipeline = pipeline;
+        pipeline.name = pipeline.name.trim().to_string();
+        pipeline.levels = pipeline.levels.into_iter().map(|(k, v)| (k.to_lowercase(), v)).collect();
+        Ok(Self { pipeline, patterns, drop })
+    }
+
+    // Разбирает message записи; остальные поля служат значениями по умолчанию
+    pub fn run(&self, mut entry: LogEntry) -> Parsed {
+        let mut fields = BTreeMap::new();
+        let caps = self.patterns.iter().find_map(|re| re.captures(&entry.message).map(|caps| (re, caps)));
+        let matched = caps.is_some();
+        if let Some((re, caps)) = caps {
+            for name in re.capture_names().flatten() {
+                let Some(value) = caps.name(name).map(|m| m.as_str().trim()).filter(|v| !v.is_empty()) else { continue };
+                let target = self.pipeline.rename.get(name).map(String::as_str).unwrap_or(name);
+                fields.insert(target.to_string(), value.to_string());
+            }
+        }
+        if !matched && self.pipeline.drop_unmatched { return Parsed { matched, fields, entry: None }; }
+
+        let mut extra = Vec::new();
+        for (name, value) in &fields {
+            match name.as_str() {
+                "message" => entry.message = truncate(value, MAX_MESSAGE_BYTES).to_string(),
+                "level" => {
+                    let key = value.to_lowercase();
+                    if let Some(level) = self.pipeline.levels.get(&key).copied().or_else(|| default_level(&key)) { entry.level = level; }
+                }
+                "category" => {
+                    if let Some(category) = LogCategory::parse(value) { entry.category = category; }
+                }
+                "source" => entry.source = truncate(value, MAX_FIELD_BYTES).to_string(),
+                "user" => entry.user = Some(truncate(value, MAX_FIELD_BYTES).to_string()),
+                "details" => entry.details = Some(value.clone()),
+                // Не адрес — значит, выражение захватило лишнее; значение сохраняется в details
+                "ip" if value.parse::<IpAddr>().is_ok() => entry.ip = Some(value.clone()),
+                "timestamp" if parse_timestamp(value).is_some_and(|ts| ts <= Utc::now() + MAX_CLOCK_SKEW) => {
+                    entry.timestamp = parse_timestamp(value).expect("checked above");
+                }
+                _ => extra.push(format!("{}={}", name, value)),
+            }
+        }
+        if !extra.is_empty() {
+            let details = entry.details.take().into_iter().chain(extra).collect::<Vec<_>>().join("\n");
+            entry.details = Some(truncate(&details, MAX_DETAILS_BYTES).to_string());
+        }
+
+        let dropped = self.drop.iter().any(|(field, re)| {
+            fields.get(field).cloned().or_else(|| standard_field(&entry, field)).is_some_and(|v| re.is_match(&v))
+        });
+        Parsed { matched, fields, entry: (!dropped).then_some(entry) }
+    }
+}
+
+// Конвейеры, общие для всех источников записей; чтение синхронное, чтобы ими
+// пользовались и фоновые потоки (чтение файлов)
+#[derive(Clone)]
+pub struct Pipelines {
+    inner: Arc<RwLock<BTreeMap<String, Arc<Compiled>>>>,
+    db: Collection,
+}
+
+impl std::fmt::Debug for Pipelines {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pipelines").field("names", &self.inner.read().expect("pipelines lock").keys().collect::<Vec<_>>()).finish()
+    }
+}
+
+impl Pipelines {
+    pub fn open(db: &Db) -> Self {
+        let db = Collection::new(db, "log_pipelines");
+        let stored = db.load_or_seed(|p: &Pipeline| p.name.clone(), Vec::new);
+        let compiled = stored.into_iter().filter_map(|p| {
+            let name = p.name.clone();
+            Compiled::new(p).map_err(|e| tracing::error!(pipeline = %name, error = %e, "Skipping invalid log pipeline")).ok()
+        }).map(|c| (c.pipeline.name.clone(), Arc::new(c))).collect();
+        Self { inner: Arc::new(RwLock::new(compiled)), db }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Compiled>> {
+        self.inner.read().expect("pipelines lock").get(name).cloned()
+    }
+
+    // Запись после конвейера `name`; None — запись отброшена. Неизвестный конвейер
+    // (например, удалённый, но ещё указанный в настройках) пропускает запись как есть.
+    pub fn apply(&self, name: Option<&str>, entry: LogEntry) -> Option<LogEntry> {
+        match name.and_then(|n| self.get(n)) {
+            Some(pipeline) => pipeline.run(entry).entry,
+            None => Some(entry),
+        }
+    }
+
+    fn list(&self) -> Vec<Pipeline> {
+        self.inner.read().expect("pipelines lock").values().map(|c| c.pipeline.clone()).collect()
+    }
+
+    fn put(&self, compiled: Compiled) -> Pipeline {
+        let pipeline = compiled.pipeline.clone();
+        self.db.put(&pipeline.name, &pipeline);
+        self.inner.write().expect("pipelines lock").insert(pipeline.name.clone(), Arc::new(compiled));
+        pipeline
+    }
+
+    fn remove(&self, name: &str) -> bool {
+        let removed = self.inner.write().expect("pipelines lock").remove(name).is_some();
+        if removed { self.db.remove(name); }
+        removed
+    }
+}
+
+pub async fn list_pipelines(State(store): State<LogsStore>) -> Json<Vec<Pipeline>> {
+    Json(store.pipelines().list())
+}
+
+pub async fn create_pipeline(State(store): State<LogsStore>, Json(payload): Json<Pipeline>) -> impl IntoResponse {
+    let compiled = match Compiled::new(payload) {
+        Ok(c) => c,
+        Err(e) => return (StatusCode::UNPROCESSABLE_ENTITY, e).into_response(),
+    };
+    if store.pipelines().get(&compiled.pipeline.name).is_some() {
+        return (StatusCode::CONFLICT, "Pipeline already exists").into_response();
+    }
+    (StatusCode::CREATED, Json(store.pipelines().put(compiled))).into_response()
+}
+
+// Имя берётся из пути; имя в теле игнорируется
+pub async fn update_pipeline(State(store): State<LogsStore>, Path(name): Path<String>, Json(mut payload): Json<Pipeline>) -> impl IntoResponse {
+    if store.pipelines().get(&name).is_none() { return (StatusCode::NOT_FOUND, "Not found").into_response(); }
+    payload.name = name;
+    match Compiled::new(payload) {
+        Ok(compiled) => Json(store.pipelines().put(compiled)).into_response(),
+        Err(e) => (StatusCode::UNPROCESSABLE_ENTITY, e).into_response(),
+    }
+}
+
+pub async fn delete_pipeline(State(store): State<LogsStore>, Path(name): Path<String>) -> impl IntoResponse {
+    if store.pipelines().remove(&name) { StatusCode::NO_CONTENT.into_response() } else { (StatusCode::NOT_FOUND, "Not found").into_response() }
+}
+
+// Проверка на примерах: сохранённый конвейер по имени или ещё не сохранённое определение
+#[derive(Debug, Deserialize)]
+pub struct PipelineTest {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub pipeline: Option<Pipeline>,
+    pub lines: Vec<String>,
+    #[serde(default)]
+    pub category: Option<LogCategory>,
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LineResult {
+    pub line: String,
+    #[serde(flatten)]
+    pub parsed: Parsed,
+}
+
+// Ничего не записывает в журнал
+pub async fn test_pipeline(State(store): State<LogsStore>, Json(payload): Json<PipelineTest>) -> impl IntoResponse {
+    if payload.lines.len() > MAX_TEST_LINES {
+        return (StatusCode::UNPROCESSABLE_ENTITY, format!("lines exceeds {} entries", MAX_TEST_LINES)).into_response();
+    }
+    let compiled = match (payload.pipeline, payload.name) {
+        (Some(mut pipeline), _) => {
+            if pipeline.name.is_empty() { pipeline.name = "test".into(); }
+            match Compiled::new(pipeline) {
+                Ok(c) => Arc::new(c),
+                Err(e) => return (StatusCode::UNPROCESSABLE_ENTITY, e).into_response(),
+            }
+        }
+        (None, Some(name)) => match store.pipelines().get(&name) {
+            Some(c) => c,
+            None => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+        },
+        (None, None) => return (StatusCode::UNPROCESSABLE_ENTITY, "either name or pipeline is required".to_string()).into_response(),
+    };
+    let category = payload.category.unwrap_or(LogCategory::System);
+    let source = payload.source.unwrap_or_else(|| "pipeline-test".into());
+    let results: Vec<LineResult> = payload.lines.into_iter().map(|line| {
+        let entry = LogEntry::new(LogLevel::Info, category, truncate(&line, MAX_MESSAGE_BYTES), source.clone());
+        LineResult { parsed: compiled.run(entry), line }
+    }).collect();
+    Json(results).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::to_bytes;
+    use serde_json::{json, Value};
+    use super::*;
+    use crate::{logs::LogFilter, storage::MemoryStorage};
+
+    #[test]
+    fn expands_grok_references() {
+        assert_eq!(expand_grok(r"%{INT:status} took %{NUMBER}ms").unwrap(), r"(?P<status>[+-]?\d+) took (?:[+-]?(?:\d+(?:\.\d*)?|\.\d+))ms");
+        assert_eq!(expand_grok(r"^(?P<raw>\w+)$").unwrap(), r"^(?P<raw>\w+)$");
+        assert_eq!(expand_grok("%{NOPE:x}").unwrap_err(), "unknown grok pattern %{NOPE}");
+        let re = Regex::new(&expand_grok("%{IP:ip} %{WORD:method} %{URIPATHPARAM:path}").unwrap()).unwrap();
+        let caps = re.captures("10.1.2.3 GET /api/logs?level=error").unwrap();
+        assert_eq!((&caps["ip"], &caps["method"], &caps["path"]), ("10.1.2.3", "GET", "/api/logs?level=error"));
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_maps_fields_to_entry() {
+        let db: Db = Arc::new(MemoryStorage::default());
+        let store = LogsStore::open_in(&db, None);
+        let payload = json!({
+            "pipeline": {
+                "patterns": [r"^%{TIMESTAMP_ISO8601:timestamp} %{LOGLEVEL:lvl} \[%{WORD:category}\] %{IP:ip} %{USERNAME:who} %{POSINT:took}ms %{GREEDYDATA:message}$"],
+                "rename": { "lvl": "level", "who": "user" },
+                "levels": { "WARN": "error" },
+                "drop": [{ "field": "user", "pattern": "^healthcheck$" }]
+            },
+            "lines": [
+                "2024-05-01T10:00:00Z WARN [Database] 10.0.0.7 alice 120ms slow query",
+                "2024-05-01T10:00:01Z info [api] 10.0.0.8 healthcheck 1ms ping",
+                "not matching at all"
+            ],
+            "source": "app"
+        });
+        let res = test_pipeline(State(store.clone()), Json(serde_json::from_value(payload).unwrap())).await.into_response();
+        assert_eq!(res.status(), StatusCode::OK);
+        let results: Vec<Value> = serde_json::from_slice(&to_bytes(res.into_body(), 1 << 20).await.unwrap()).unwrap();
+
+        let first = &results[0];
+        assert_eq!(first["matched"], true);
+        assert_eq!(first["fields"]["level"], "WARN");
+        assert_eq!(first["fields"]["user"], "alice");
+        let entry = &first["entry"];
+        // Своё отображение уровней важнее стандартного, категория — без учёта регистра
+        assert_eq!((&entry["level"], &entry["category"]), (&json!("error"), &json!("database")));
+        assert_eq!((&entry["ip"], &entry["user"], &entry["message"]), (&json!("10.0.0.7"), &json!("alice"), &json!("slow query")));
+        assert_eq!(entry["timestamp"], "2024-05-01T10:00:00Z");
+        assert_eq!((&entry["source"], &entry["details"]), (&json!("app"), &json!("took=120")));
+
+        assert_eq!(results[1]["matched"], true);
+        assert!(results[1]["entry"].is_null());
+        assert_eq!(results[2]["matched"], false);
+        assert_eq!(results[2]["entry"]["message"], "not matching at all");
+        // Проверка ничего не пишет в журнал
+        assert!(store.query(&LogFilter::default().compile().unwrap(), None, 100).await.0.iter().all(|l| l.source != "app"));
+    }
+}
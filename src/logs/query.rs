@@ -0,0 +1,187 @@
+use std::net::IpAddr;
+use axum::{
+    extract::{Query, State},
+    http::{HeaderName, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use ipnet::IpNet;
+use serde::{Deserialize, Deserializer, Serialize};
+use super::{LogCategory, LogEntry, LogLevel, LogsStore};
+use crate::ip_filter;
+
+pub const DEFAULT_LIMIT: usize = 100;
+pub const MAX_LIMIT: usize = 1000;
+pub const NEXT_CURSOR: HeaderName = HeaderName::from_static("x-next-cursor");
+
+// Список значений: "error,warning" в строке запроса или массив в JSON
+fn de_list<'de, D, T>(d: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: for<'a> Deserialize<'a>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw { One(String), Many(Vec<String>) }
+    let items = match Option::<Raw>::deserialize(d)? {
+        None => return Ok(Vec::new()),
+        Some(Raw::One(s)) => s.split(',').map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect(),
+        Some(Raw::Many(v)) => v,
+    };
+    items.into_iter()
+        .map(|v| T::deserialize(serde::de::value::StringDeserializer::<serde::de::value::Error>::new(v)).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+// Фильтр журнала. Общий для списка, потока, экспорта и статистики.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogFilter {
+    #[serde(default, deserialize_with = "de_list", skip_serializing_if = "Vec::is_empty")]
+    pub level: Vec<LogLevel>,
+    #[serde(default, deserialize_with = "de_list", skip_serializing_if = "Vec::is_empty")]
+    pub category: Vec<LogCategory>,
+    // [from, to)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    // Адрес или диапазон CIDR
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    // Подстрока без учёта регистра
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    // Полнотекстовый поиск по message и details: все слова должны встретиться
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+// Подготовленный фильтр: разобранный CIDR и слова поиска в нижнем регистре
+#[derive(Debug, Clone)]
+pub struct Matcher { filter: LogFilter, ip: Option<IpNet>, source: Option<String>, terms: Vec<String> }
+
+impl LogFilter {
+    pub fn compile(&self) -> Result<Matcher, String> {
+        let ip = self.ip.as_deref().filter(|s| !s.trim().is_empty()).map(ip_filter::parse_rule).transpose()?;
+        if let (Some(from), Some(to)) = (self.from, self.to) && from >= to {
+            return Err("from must be earlier than to".into());
+        }
+        let terms = self.q.as_deref().unwrap_or_default().split_whitespace().map(str::to_lowercase).collect();
+        let source = self.source.as_deref().map(str::trim).filter(|s| !s.is_empty()).map(str::to_lowercase);
+        Ok(Matcher { filter: self.clone(), ip, source, terms })
+    }
+}
+
+impl Matcher {
+    // Интервал времени фильтра — позволяет не читать сегменты вне него
+    pub fn range(&self) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) { (self.filter.from, self.filter.to) }
+
+    // Пустой список — любые
+    pub fn levels(&self) -> &[LogLevel] { &self.filter.level }
+
+    pub fn categories(&self) -> &[LogCategory] { &self.filter.category }
+
+    pub fn allows(&self, level: LogLevel, category: LogCategory) -> bool {
+        (self.filter.level.is_empty() || self.filter.level.contains(&level)) && (self.filter.category.is_empty() || self.filter.category.contains(&category))
+    }
+
+    // Фильтр только по уровню, категории и времени: такие выборки считаются по
+    // счётчикам и индексам, без чтения самих записей
+    pub fn is_simple(&self) -> bool {
+        let f = &self.filter;
+        f.user.as_deref().is_none_or(str::is_empty) && self.ip.is_none() && self.source.is_none()
+            && f.request_id.as_deref().is_none_or(str::is_empty) && self.terms.is_empty()
+    }
+
+    pub fn matches(&self, log: &LogEntry) -> bool {
+        let f = &self.filter;
+        if !f.level.is_empty() && !f.level.contains(&log.level) { return false; }
+        if !f.category.is_empty() && !f.category.contains(&log.category) { return false; }
+        if f.from.is_some_and(|from| log.timestamp < from) { return false; }
+        if f.to.is_some_and(|to| log.timestamp >= to) { return false; }
+        if let Some(user) = f.user.as_deref().filter(|u| !u.is_empty()) && log.user.as_deref() != Some(user) { return false; }
+        if let Some(net) = &self.ip {
+            let ip = log.ip.as_deref().and_then(|ip| ip.parse::<IpAddr>().ok());
+            if !ip.is_some_and(|ip| net.contains(&ip)) { return false; }
+        }
+        if let Some(source) = &self.source && !log.source.to_lowercase().contains(source) { return false; }
+        if let Some(id) = f.request_id.as_deref().filter(|id| !id.is_empty()) && log.request_id.as_deref() != Some(id) { return false; }
+        if !self.terms.is_empty() {
+            let text = format!("{}\n{}", log.message, log.details.as_deref().unwrap_or_default()).to_lowercase();
+            if !self.terms.iter().all(|t| text.contains(t)) { return false; }
+        }
+        true
+    }
+}
+
+// Курсор — seq последней полученной записи; следующая страница начинается с более старых
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Page {
+    #[serde(default)]
+    pub cursor: Option<u64>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+pub async fn list_logs(State(store): State<LogsStore>, Query(filter): Query<LogFilter>, Query(page): Query<Page>) -> impl IntoResponse {
+    let matcher = match filter.compile() {
+        Ok(m) => m,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let limit = page.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let (items, next) = store.query(&matcher, page.cursor, limit).await;
+    let mut res = Json(items).into_response();
+    if let Some(next) = next { res.headers_mut().insert(NEXT_CURSOR, HeaderValue::from(next)); }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use axum::{body::{to_bytes, Body}, extract::Request, http::Response, routing::get, Router};
+    use tower::ServiceExt;
+    use super::*;
+    use crate::storage::{Db, MemoryStorage};
+
+    async fn get_logs(store: &LogsStore, uri: &str) -> Response<Body> {
+        let app = Router::new().route("/logs", get(list_logs)).with_state(store.clone());
+        app.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn pages_follow_next_cursor_to_the_end() {
+        let db: Db = Arc::new(MemoryStorage::default());
+        let store = LogsStore::open_in(&db, None);
+        store.append_many((0..25).map(|i| {
+            let level = if i % 5 == 0 { LogLevel::Error } else { LogLevel::Info };
+            LogEntry::new(level, LogCategory::Api, format!("request {}", i), "api.rs:1")
+        }).collect()).await;
+
+        let mut seen = Vec::new();
+        let mut uri = "/logs?category=api&limit=10".to_string();
+        loop {
+            let res = get_logs(&store, &uri).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            let next = res.headers().get(&NEXT_CURSOR).map(|v| v.to_str().unwrap().to_string());
+            let page: Vec<serde_json::Value> = serde_json::from_slice(&to_bytes(res.into_body(), 1 << 20).await.unwrap()).unwrap();
+            seen.extend(page.iter().map(|l| l["message"].as_str().unwrap().to_string()));
+            match next {
+                Some(cursor) => uri = format!("/logs?category=api&limit=10&cursor={}", cursor),
+                None => break,
+            }
+        }
+        let expected: Vec<String> = (0..25).rev().map(|i| format!("request {}", i)).collect();
+        assert_eq!(seen, expected);
+
+        let res = get_logs(&store, "/logs?level=error,warning&category=api&q=REQUEST").await;
+        assert!(res.headers().get(&NEXT_CURSOR).is_none());
+        let page: Vec<serde_json::Value> = serde_json::from_slice(&to_bytes(res.into_body(), 1 << 20).await.unwrap()).unwrap();
+        assert_eq!(page.len(), 5);
+        assert_eq!(get_logs(&store, "/logs?from=2024-02-01T00:00:00Z&to=2024-01-01T00:00:00Z").await.status(), StatusCode::BAD_REQUEST);
+    }
+}
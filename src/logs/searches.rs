@@ -0,0 +1,264 @@
+use std::{collections::HashMap, sync::Arc};
+use axum::{extract::{Path, State}, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, RwLock};
+use uuid::Uuid;
+use super::{LogFilter, LogsStore};
+use crate::{auth::CurrentUser, permissions::ALL, storage::{Collection, Db}, users::User};
+
+// Колонки таблицы журнала — поля LogEntry
+const COLUMNS: &[&str] = &["timestamp", "level", "category", "message", "details", "ip", "user", "source", "request_id", "seq", "id", "repeat"];
+const MAX_NAME: usize = 100;
+
+// Сохранённый поиск: фильтр, колонки и диапазон времени. Виден владельцу, а с
+// shared — всем; служит источником записей для правил оповещений и выгрузки.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub id: Uuid,
+    pub name: String,
+    pub owner: Uuid,
+    pub owner_name: String,
+    pub shared: bool,
+    pub filter: LogFilter,
+    // Порядок колонок в таблице; пусто — колонки по умолчанию
+    pub columns: Vec<String>,
+    // Последние N секунд на момент применения; абсолютный диапазон задают from/to фильтра
+    pub last_secs: Option<u64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpsertSearch {
+    pub name: String,
+    #[serde(default)]
+    pub shared: bool,
+    #[serde(default)]
+    pub filter: LogFilter,
+    #[serde(default)]
+    pub columns: Vec<String>,
+    #[serde(default)]
+    pub last_secs: Option<u64>,
+}
+
+impl UpsertSearch {
+    fn validate(&self) -> Result<(), String> {
+        let name = self.name.trim();
+        if name.is_empty() { return Err("name must not be empty".into()); }
+        if name.len() > MAX_NAME { return Err(format!("name exceeds {} bytes", MAX_NAME)); }
+        if let Some(column) = self.columns.iter().find(|c| !COLUMNS.contains(&c.as_str())) {
+            return Err(format!("unknown column: {}", column));
+        }
+        if self.last_secs == Some(0) { return Err("last_secs must be positive".into()); }
+        if self.last_secs.is_some() && (self.filter.from.is_some() || self.filter.to.is_some()) {
+            return Err("last_secs cannot be combined with filter.from/filter.to".into());
+        }
+        self.filter.compile().map(|_| ())
+    }
+}
+
+impl SavedSearch {
+    // Фильтр на момент `now`: относительный диапазон превращается в from
+    pub fn resolve(&self, now: DateTime<Utc>) -> LogFilter {
+        let mut filter = self.filter.clone();
+        if let Some(secs) = self.last_secs { filter.from = Some(now - Duration::seconds(secs as i64)); }
+        filter
+    }
+
+    pub fn visible_to(&self, user: Option<&User>) -> bool {
+        self.shared || user.is_some_and(|u| u.id == self.owner)
+    }
+
+    // Общие поиски может править и администратор; личные — только владелец
+    fn editable_by(&self, user: &User) -> bool {
+        user.id == self.owner || (self.shared && user.has_permission(ALL))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SavedSearches {
+    items: Arc<RwLock<Vec<SavedSearch>>>,
+    db: Collection,
+    changes: Arc<watch::Sender<Vec<SavedSearch>>>,
+    // Имена правил оповещений по id поиска, на котором они построены; ведёт AlertsStore
+    rules: Arc<std::sync::RwLock<HashMap<Uuid, Vec<String>>>>,
+}
+
+impl SavedSearches {
+    pub fn open(db: &Db) -> Self {
+        let db = Collection::new(db, "saved_searches");
+        let items = db.load_or_seed(|s: &SavedSearch| s.id.to_string(), Vec::new);
+        Self { changes: Arc::new(watch::Sender::new(items.clone())), items: Arc::new(RwLock::new(items)), db, rules: Arc::default() }
+    }
+
+    // Поиск, на котором построены правила, нельзя удалить, пока они есть
+    pub fn set_rules(&self, rules: HashMap<Uuid, Vec<String>>) {
+        *self.rules.write().unwrap() = rules;
+    }
+
+    pub async fn visible_to(&self, user: &User) -> Vec<SavedSearch> {
+        self.items.read().await.iter().filter(|s| s.visible_to(Some(user))).cloned().collect()
+    }
+
+    pub async fn get(&self, id: Uuid) -> Option<SavedSearch> {
+        self.items.read().await.iter().find(|s| s.id == id).cloned()
+    }
+
+    // Изменения нужны правилам оповещений, построенным на поисках
+    pub fn subscribe(&self) -> watch::Receiver<Vec<SavedSearch>> { self.changes.subscribe() }
+
+    async fn publish(&self) {
+        self.changes.send_replace(self.items.read().await.clone());
+    }
+}
+
+pub async fn list_searches(State(store): State<LogsStore>, user: Option<CurrentUser>) -> Json<Vec<SavedSearch>> {
+    let user = user.map(|CurrentUser(u)| u);
+    let items = store.searches().items.read().await;
+    Json(items.iter().filter(|s| s.visible_to(user.as_ref())).cloned().collect())
+}
+
+pub async fn get_search(State(store): State<LogsStore>, user: Option<CurrentUser>, Path(id): Path<Uuid>) -> impl IntoResponse {
+    let user = user.map(|CurrentUser(u)| u);
+    match store.searches().get(id).await.filter(|s| s.visible_to(user.as_ref())) {
+        Some(search) => Json(search).into_response(),
+        None => (StatusCode::NOT_FOUND, "Not found").into_response(),
+    }
+}
+
+pub async fn create_search(State(store): State<LogsStore>, CurrentUser(user): CurrentUser, Json(payload): Json<UpsertSearch>) -> impl IntoResponse {
+    if let Err(e) = payload.validate() { return (StatusCode::UNPROCESSABLE_ENTITY, e).into_response(); }
+    let now = Utc::now();
+    let search = SavedSearch {
+        id: Uuid::new_v4(),
+        name: payload.name.trim().to_string(),
+        owner: user.id,
+        owner_name: user.username.clone(),
+        shared: payload.shared,
+        filter: payload.filter,
+        columns: payload.columns,
+        last_secs: payload.last_secs,
+        created_at: now,
+        updated_at: now,
+    };
+    let searches = store.searches();
+    searches.items.write().await.push(search.clone());
+    searches.db.put(&search.id.to_string(), &search);
+    searches.publish().await;
+    (StatusCode::CREATED, Json(search)).into_response()
+}
+
+pub async fn update_search(State(store): State<LogsStore>, CurrentUser(user): CurrentUser, Path(id): Path<Uuid>, Json(payload): Json<UpsertSearch>) -> impl IntoResponse {
+    if let Err(e) = payload.validate() { return (StatusCode::UNPROCESSABLE_ENTITY, e).into_response(); }
+    let searches = store.searches();
+    let search = {
+        let mut items = searches.items.write().await;
+        let Some(item) = items.iter_mut().find(|s| s.id == id && s.visible_to(Some(&user))) else { return (StatusCode::NOT_FOUND, "Not found").into_response() };
+        if !item.editable_by(&user) { return (StatusCode::FORBIDDEN, "Only the owner can change this search").into_response(); }
+        item.name = payload.name.trim().to_string();
+        item.shared = payload.shared;
+        item.filter = payload.filter;
+        item.columns = payload.columns;
+        item.last_secs = payload.last_secs;
+        item.updated_at = Utc::now();
+        searches.db.put(&id.to_string(), &*item);
+        item.clone()
+    };
+    searches.publish().await;
+    Json(search).into_response()
+}
+
+pub async fn delete_search(State(store): State<LogsStore>, CurrentUser(user): CurrentUser, Path(id): Path<Uuid>) -> impl IntoResponse {
+    let searches = store.searches();
+    {
+        let mut items = searches.items.write().await;
+        let Some(pos) = items.iter().position(|s| s.id == id && s.visible_to(Some(&user))) else { return (StatusCode::NOT_FOUND, "Not found").into_response() };
+        if !items[pos].editable_by(&user) { return (StatusCode::FORBIDDEN, "Only the owner can delete this search").into_response(); }
+        if let Some(rules) = searches.rules.read().unwrap().get(&id) {
+            return (StatusCode::CONFLICT, format!("Search is used by alert rules: {}", rules.join(", "))).into_response();
+        }
+        items.remove(pos);
+        searches.db.remove(&id.to_string());
+    }
+    searches.publish().await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::to_bytes, response::Response};
+    use super::*;
+    use crate::{
+        alerts::{create_rule, delete_rule, AlertsStore},
+        storage::MemoryStorage,
+        users::{Credentials, UserRole, UserStatus},
+    };
+
+    fn user(name: &str, role: UserRole) -> User {
+        User {
+            id: Uuid::new_v4(), username: name.into(), email: format!("{}@example.com", name), full_name: name.into(), role, status: UserStatus::Active,
+            join_date: Utc::now(), last_activity: String::new(), posts: 0, reputation: 0, permissions: Vec::new(), credentials: Credentials::default(),
+        }
+    }
+
+    fn search(owner: &User, shared: bool) -> SavedSearch {
+        let now = Utc::now();
+        SavedSearch {
+            id: Uuid::new_v4(), name: "errors".into(), owner: owner.id, owner_name: owner.username.clone(), shared,
+            filter: LogFilter::default(), columns: Vec::new(), last_secs: None, created_at: now, updated_at: now,
+        }
+    }
+
+    async fn body(res: Response) -> String {
+        String::from_utf8(to_bytes(res.into_body(), 4096).await.unwrap().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn private_searches_belong_to_their_owner() {
+        let (owner, other, admin) = (user("owner", UserRole::User), user("other", UserRole::User), user("admin", UserRole::Admin));
+        let private = search(&owner, false);
+        assert!(private.visible_to(Some(&owner)) && private.editable_by(&owner));
+        assert!(!private.visible_to(Some(&other)) && !private.visible_to(Some(&admin)) && !private.visible_to(None));
+        assert!(!private.editable_by(&admin));
+
+        let shared = search(&owner, true);
+        assert!(shared.visible_to(Some(&other)) && shared.visible_to(None));
+        assert!(!shared.editable_by(&other));
+        assert!(shared.editable_by(&admin));
+    }
+
+    #[test]
+    fn relative_range_resolves_against_now() {
+        let owner = user("owner", UserRole::User);
+        let now = Utc::now();
+        let fixed = search(&owner, false);
+        assert_eq!(fixed.resolve(now).from, None);
+        let recent = SavedSearch { last_secs: Some(900), ..fixed };
+        assert_eq!(recent.resolve(now).from, Some(now - Duration::minutes(15)));
+        assert_eq!(recent.resolve(now).to, None);
+    }
+
+    #[tokio::test]
+    async fn search_used_by_alert_rules_cannot_be_deleted() {
+        let db: Db = Arc::new(MemoryStorage::default());
+        let store = LogsStore::open_in(&db, None);
+        let alerts = AlertsStore::open(&db, store.searches().clone());
+        let admin = user("admin", UserRole::Admin);
+        let created = create_search(State(store.clone()), CurrentUser(admin.clone()), Json(serde_json::from_value(serde_json::json!({ "name": "errors" })).unwrap())).await.into_response();
+        let id: Uuid = serde_json::from_str::<SavedSearch>(&body(created).await).unwrap().id;
+        let rule = serde_json::from_value(serde_json::json!({ "name": "error burst", "search_id": id })).unwrap();
+        let rule = create_rule(State(alerts.clone()), CurrentUser(admin.clone()), Json(rule)).await.into_response();
+        assert_eq!(rule.status(), StatusCode::CREATED);
+        let rule_id: Uuid = serde_json::from_str::<serde_json::Value>(&body(rule).await).unwrap()["id"].as_str().unwrap().parse().unwrap();
+
+        let refused = delete_search(State(store.clone()), CurrentUser(admin.clone()), Path(id)).await.into_response();
+        assert_eq!(refused.status(), StatusCode::CONFLICT);
+        assert_eq!(body(refused).await, "Search is used by alert rules: error burst");
+        assert!(store.searches().get(id).await.is_some());
+
+        assert_eq!(delete_rule(State(alerts), Path(rule_id)).await.into_response().status(), StatusCode::NO_CONTENT);
+        let deleted = delete_search(State(store.clone()), CurrentUser(admin), Path(id)).await.into_response();
+        assert_eq!(deleted.status(), StatusCode::NO_CONTENT);
+    }
+}
@@ -0,0 +1,522 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use super::{index::Counts, LogCategory, LogEntry, LogLevel, Matcher, Repeat};
+use tokio::sync::{mpsc, oneshot};
+use crate::settings::LogsConfig;
+
+const EXT: &str = "log";
+// Описание сегмента рядом с ним: при запуске читается вместо разбора всего файла
+const META_EXT: &str = "meta";
+// Последний seq на момент прохода политики хранения: нумерация продолжается с него, даже если сегментов не осталось
+const SEQ_FILE: &str = "seq";
+const MB: u64 = 1024 * 1024;
+const AMEND_PREFIX: &str = "{\"amend\":";
+// Сегмент закрывается не реже раза в сутки, чтобы старые записи не задерживались в открытом сегменте
+const MAX_SEGMENT_SPAN: Duration = Duration::days(1);
+
+// Файл сегмента: по записи JSON на строку в порядке seq. Имя — seq первой записи.
+// Итоговый счётчик повторов дописывается в конец отдельной строкой-поправкой (AMEND_PREFIX),
+// которая при чтении применяется к записи с тем же seq; сжатие сворачивает поправки в записи.
+// Описание сохраняется в файл .meta, когда сегмент закрывается, сжимается или получает поправки;
+// оно действительно, пока размер файла совпадает с bytes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Segment {
+    #[serde(skip)]
+    path: PathBuf,
+    first_seq: u64,
+    last_seq: u64,
+    count: usize,
+    bytes: u64,
+    opened_at: DateTime<Utc>,
+    min_ts: DateTime<Utc>,
+    max_ts: DateTime<Utc>,
+    // Самая старая запись каждой категории — по ней видно, пора ли сжимать сегмент
+    oldest: HashMap<LogCategory, DateTime<Utc>>,
+    // По ним статистика не читает сегмент, а поиск пропускает сегменты без подходящих записей
+    counts: Counts,
+}
+
+impl Segment {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            first_seq: u64::MAX,
+            last_seq: 0,
+            count: 0,
+            bytes: 0,
+            opened_at: Utc::now(),
+            min_ts: DateTime::<Utc>::MAX_UTC,
+            max_ts: DateTime::<Utc>::MIN_UTC,
+            oldest: HashMap::new(),
+            counts: Counts::default(),
+        }
+    }
+
+    fn track(&mut self, log: &LogEntry, bytes: u64) {
+        self.first_seq = self.first_seq.min(log.seq);
+        self.last_seq = self.last_seq.max(log.seq);
+        self.count += 1;
+        self.bytes += bytes;
+        self.min_ts = self.min_ts.min(log.timestamp);
+        self.max_ts = self.max_ts.max(log.timestamp);
+        let oldest = self.oldest.entry(log.category).or_insert(log.timestamp);
+        *oldest = (*oldest).min(log.timestamp);
+        self.counts.add(log);
+    }
+
+    fn overlaps(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> bool {
+        from.is_none_or(|from| self.max_ts >= from) && to.is_none_or(|to| self.min_ts < to)
+    }
+
+    fn within(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> bool {
+        from.is_none_or(|from| self.min_ts >= from) && to.is_none_or(|to| self.max_ts < to)
+    }
+
+    // Может ли в сегменте быть подходящая запись
+    fn may_match(&self, matcher: &Matcher) -> bool {
+        let (from, to) = matcher.range();
+        self.overlaps(from, to) && !self.counts.matching(matcher).is_empty()
+    }
+
+    fn read(&self) -> Vec<LogEntry> { read(&self.path) }
+
+    fn meta_path(&self) -> PathBuf { self.path.with_extension(META_EXT) }
+
+    fn save_meta(&self) {
+        let body = serde_json::to_string(self).expect("serializable segment description");
+        if let Err(e) = fs::write(self.meta_path(), body) {
+            tracing::error!(path = %self.path.display(), error = %e, "Failed to save log segment description");
+        }
+    }
+
+    // Описание из .meta или, если его нет либо файл с тех пор менялся, — разбором сегмента
+    fn load(path: PathBuf) -> Self {
+        let bytes = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        let meta = path.with_extension(META_EXT);
+        if let Some(mut segment) = fs::read_to_string(&meta).ok().and_then(|body| serde_json::from_str::<Segment>(&body).ok()).filter(|s| s.bytes == bytes) {
+            segment.path = path;
+            return segment;
+        }
+        let mut segment = Segment::new(path);
+        for log in segment.read() { segment.track(&log, 0); }
+        segment.bytes = bytes;
+        if segment.count > 0 { segment.save_meta(); }
+        segment
+    }
+
+    fn file(&self) -> SegmentFile {
+        SegmentFile { path: self.path.clone(), first_seq: self.first_seq, last_seq: self.last_seq }
+    }
+}
+
+fn read(path: &Path) -> Vec<LogEntry> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        // Сегмент удалён политикой хранения, пока его читали без блокировки
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => { tracing::error!(path = %path.display(), error = %e, "Failed to read log segment"); return Vec::new(); }
+    };
+    // Недописанная строка (аварийная остановка или дозапись во время чтения) просто пропускается
+    let mut logs: Vec<LogEntry> = Vec::new();
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        if !line.starts_with(AMEND_PREFIX) {
+            logs.extend(serde_json::from_str(&line).ok());
+            continue;
+        }
+        let Ok(amend) = serde_json::from_str::<Amend>(&line) else { continue };
+        if let Ok(i) = logs.binary_search_by_key(&amend.amend, |l| l.seq) { logs[i].repeat = Some(amend.repeat); }
+    }
+    logs
+}
+
+// Строка-поправка: итоговый счётчик повторов записи amend
+#[derive(Debug, Serialize, Deserialize)]
+struct Amend { amend: u64, repeat: Repeat }
+
+// Сегмент, выбранный под блокировкой и читаемый уже без неё (в пуле блокирующих задач).
+// Открытый сегмент может за это время дополниться — лишние записи отбрасывает вызывающий по seq.
+#[derive(Debug, Clone)]
+pub struct SegmentFile { path: PathBuf, pub first_seq: u64, pub last_seq: u64 }
+
+impl SegmentFile {
+    pub fn read(&self) -> Vec<LogEntry> { read(&self.path) }
+}
+
+fn line(log: &LogEntry) -> String {
+    let mut line = serde_json::to_string(log).expect("serializable log entry");
+    line.push('\n');
+    line
+}
+
+// Записи, которые по политике хранения пора удалить
+pub fn expired(policy: &LogsConfig, log: &LogEntry, now: DateTime<Utc>) -> bool {
+    cutoff(policy, log.category, now).is_some_and(|cutoff| log.timestamp < cutoff)
+}
+
+fn cutoff(policy: &LogsConfig, category: LogCategory, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let days = policy.category_max_age_days.get(&category).copied().unwrap_or(policy.max_age_days);
+    (days > 0).then(|| now - Duration::days(days as i64))
+}
+
+// Итог прохода политики хранения
+#[derive(Debug, Default)]
+pub struct Retained {
+    pub deleted: usize,
+    pub compacted: usize,
+    pub dropped: usize,
+    // Самый старый seq, оставшийся на диске
+    pub oldest_seq: Option<u64>,
+}
+
+#[derive(Debug)]
+struct State {
+    segments: Vec<Segment>,
+    // Последний seq, переданный на запись: всё, что не новее, уже в сегментах
+    persisted: u64,
+    // Сохранённый в SEQ_FILE
+    floor: u64,
+}
+
+// Счётчики для статистики по записям старше окна в памяти: сегменты целиком внутри
+// диапазона уже посчитаны, граничные нужно прочитать. Сегмент, который заходит в окно,
+// посчитан целиком — записи окна до его last_seq вызывающий вычитает.
+#[derive(Debug)]
+pub struct Tally { pub counts: Counts, pub partial: Vec<SegmentFile>, pub overlap: Option<u64> }
+
+// Append-only сегменты журнала на диске. Все записи попадают сюда, а в памяти
+// остаётся только окно последних. Без каталога (DC_STORAGE=memory) ничего не пишется.
+// Файлы меняет только Writer; под блокировкой здесь лишь описание сегментов.
+#[derive(Debug)]
+pub struct Segments { dir: Option<PathBuf>, state: Mutex<State>, stopped: AtomicBool }
+
+impl Segments {
+    pub fn open(dir: Option<PathBuf>) -> Self {
+        let mut segments: Vec<Segment> = Vec::new();
+        if let Some(dir) = &dir {
+            fs::create_dir_all(dir).expect("failed to create log segments directory");
+            let mut paths: Vec<(u64, PathBuf)> = fs::read_dir(dir).expect("failed to read log segments directory")
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter_map(|path| {
+                    // Остатки прерванного сжатия и описания удалённых сегментов
+                    if path.extension().is_some_and(|ext| ext == "tmp") { let _ = fs::remove_file(&path); return None; }
+                    if path.extension().is_some_and(|ext| ext == META_EXT) && !path.with_extension(EXT).exists() { let _ = fs::remove_file(&path); return None; }
+                    if path.extension().is_none_or(|ext| ext != EXT) { return None; }
+                    Some((path.file_stem()?.to_str()?.parse().ok()?, path))
+                })
+                .collect();
+            paths.sort();
+            for (_, path) in paths {
+                let segment = Segment::load(path);
+                if segment.count == 0 { let _ = fs::remove_file(&segment.path); continue; }
+                segments.push(segment);
+            }
+        }
+        let persisted = segments.last().map(|s| s.last_seq).unwrap_or(0);
+        let floor = dir.as_ref().and_then(|d| fs::read_to_string(d.join(SEQ_FILE)).ok()).and_then(|s| s.trim().parse().ok()).unwrap_or(0);
+        Self { dir, state: Mutex::new(State { segments, persisted, floor }), stopped: AtomicBool::new(false) }
+    }
+
+    pub fn is_persistent(&self) -> bool { self.dir.is_some() }
+
+    // Поток записи остановился: журнал дальше живёт только в памяти. true — при первом вызове.
+    pub fn stop(&self) -> bool { !self.stopped.swap(true, Ordering::Relaxed) }
+
+    // В журнал на диске ещё ничего не записывалось
+    pub fn is_new(&self) -> bool {
+        let st = self.state.lock().unwrap();
+        st.segments.is_empty() && st.floor == 0
+    }
+
+    pub fn last_seq(&self) -> u64 {
+        let st = self.state.lock().unwrap();
+        st.segments.last().map(|s| s.last_seq).unwrap_or(0).max(st.floor)
+    }
+
+    // Записи не новее этого seq можно вытеснять из окна в памяти
+    pub fn persisted(&self) -> u64 {
+        if self.dir.is_none() || self.stopped.load(Ordering::Relaxed) { return u64::MAX; }
+        self.state.lock().unwrap().persisted
+    }
+
+    // Число событий с seq < before для фильтра по уровню, категории и времени.
+    // None — журнал хранится только в памяти.
+    pub fn count(&self, matcher: &Matcher, before: u64) -> Option<Tally> {
+        self.dir.as_ref()?;
+        let (from, to) = matcher.range();
+        let st = self.state.lock().unwrap();
+        let mut tally = Tally { counts: Counts::default(), partial: Vec::new(), overlap: None };
+        for segment in st.segments.iter().filter(|s| s.first_seq < before && s.may_match(matcher)) {
+            if !segment.within(from, to) { tally.partial.push(segment.file()); continue; }
+            tally.counts.merge(&segment.counts.matching(matcher));
+            if segment.last_seq >= before { tally.overlap = Some(segment.last_seq); }
+        }
+        Some(tally)
+    }
+
+    // Сегменты с записями seq < before, от новых к старым; сегменты, где по диапазону
+    // времени и счётчикам уровней и категорий нет подходящих под фильтр записей, пропускаются
+    pub fn before(&self, before: u64, matcher: Option<&Matcher>) -> Vec<SegmentFile> {
+        let st = self.state.lock().unwrap();
+        st.segments.iter().rev().filter(|s| s.first_seq < before && matcher.is_none_or(|m| s.may_match(m))).map(Segment::file).collect()
+    }
+
+    // Сегменты с записями seq > after, от старых к новым, с тем же отбором
+    pub fn after(&self, after: u64, matcher: Option<&Matcher>) -> Vec<SegmentFile> {
+        let st = self.state.lock().unwrap();
+        st.segments.iter().filter(|s| s.last_seq > after && matcher.is_none_or(|m| s.may_match(m))).map(Segment::file).collect()
+    }
+
+    fn snapshot(&self) -> Vec<Segment> { self.state.lock().unwrap().segments.clone() }
+}
+
+// Изменение журнала на диске. Выполняются по порядку отдельным потоком записи.
+#[derive(Debug)]
+pub enum Change {
+    // Новые записи и повторы уже записанных (seq, уровень, категория)
+    Append { logs: Vec<LogEntry>, repeats: Vec<(u64, LogLevel, LogCategory)> },
+    // Итоговые счётчики повторов закрытых окон
+    Amend(Vec<(u64, Repeat)>),
+    Enforce(Box<LogsConfig>, DateTime<Utc>, oneshot::Sender<Retained>),
+}
+
+// Сколько изменений может ждать записи; дальше добавление записей ждёт диск (вне блокировки окна)
+const WRITE_QUEUE: usize = 64;
+
+// Единственный, кто меняет файлы сегментов: дозапись, перезапись и удаление.
+// Файловые операции идут без блокировки Segments, она берётся только для смены описания.
+#[derive(Debug)]
+pub struct Writer { segments: Arc<Segments>, file: Option<File>, max_bytes: u64 }
+
+impl Writer {
+    pub fn new(segments: Arc<Segments>) -> Self {
+        Self { segments, file: None, max_bytes: LogsConfig::default().segment_size_mb as u64 * MB }
+    }
+
+    // Поток записи живёт, пока есть хоть один отправитель
+    pub fn spawn(mut self) -> mpsc::Sender<Change> {
+        let (tx, mut rx) = mpsc::channel(WRITE_QUEUE);
+        std::thread::Builder::new().name("log-writer".into()).spawn(move || {
+            while let Some(change) = rx.blocking_recv() {
+                match change {
+                    Change::Append { logs, repeats } => {
+                        self.append(&logs);
+                        for (seq, level, category) in repeats { self.note_repeat(seq, level, category); }
+                    }
+                    Change::Amend(repeats) => self.amend(&repeats),
+                    Change::Enforce(policy, now, reply) => { let _ = reply.send(self.enforce(&policy, now)); }
+                }
+            }
+        }).expect("failed to start log writer thread");
+        tx
+    }
+
+    // Записи пакета, попадающие в один сегмент, пишутся одним вызовом. Записи, которые
+    // не удалось записать, всё равно считаются обработанными, иначе окно в памяти росло бы без предела.
+    pub fn append(&mut self, logs: &[LogEntry]) {
+        let Some(dir) = self.segments.dir.clone() else { return };
+        let Some(last) = logs.last().map(|l| l.seq) else { return };
+        let (mut bytes, mut opened_at) = self.segments.state.lock().unwrap().segments.last().map_or((0, Utc::now()), |s| (s.bytes, s.opened_at));
+        let mut pending = String::new();
+        let mut tracked: Vec<(&LogEntry, u64)> = Vec::new();
+        for log in logs {
+            let line = line(log);
+            let rotate = self.file.is_none() || bytes + pending.len() as u64 >= self.max_bytes || Utc::now() - opened_at > MAX_SEGMENT_SPAN;
+            if rotate {
+                if !self.flush(&mut pending, &mut tracked) { break; }
+                if let Some(sealed) = self.segments.state.lock().unwrap().segments.last() { sealed.save_meta(); }
+                let path = dir.join(format!("{:020}.{}", log.seq, EXT));
+                match OpenOptions::new().create(true).append(true).open(&path) {
+                    Ok(file) => {
+                        self.file = Some(file);
+                        let segment = Segment::new(path);
+                        (bytes, opened_at) = (0, segment.opened_at);
+                        self.segments.state.lock().unwrap().segments.push(segment);
+                    }
+                    Err(e) => { tracing::error!(path = %path.display(), error = %e, "Failed to open log segment"); break; }
+                }
+            }
+            tracked.push((log, line.len() as u64));
+            pending.push_str(&line);
+        }
+        self.flush(&mut pending, &mut tracked);
+        let mut st = self.segments.state.lock().unwrap();
+        st.persisted = st.persisted.max(last);
+    }
+
+    // Дописывает накопленные строки в открытый сегмент; false — записать не удалось
+    fn flush(&mut self, pending: &mut String, tracked: &mut Vec<(&LogEntry, u64)>) -> bool {
+        if pending.is_empty() { return true; }
+        let Some(file) = self.file.as_mut() else { return false };
+        let written = file.write_all(pending.as_bytes());
+        pending.clear();
+        let mut st = self.segments.state.lock().unwrap();
+        let State { segments, persisted, .. } = &mut *st;
+        let Some(active) = segments.last_mut() else { return false };
+        let ok = match written {
+            Ok(()) => {
+                for (log, bytes) in tracked.iter() { active.track(log, *bytes); }
+                *persisted = (*persisted).max(active.last_seq);
+                true
+            }
+            Err(e) => { tracing::error!(path = %active.path.display(), error = %e, "Failed to append log segment"); false }
+        };
+        tracked.clear();
+        ok
+    }
+
+    // Повтор, схлопнутый в уже записанную запись: поправка допишется при закрытии окна, а счётчики — сразу
+    fn note_repeat(&self, seq: u64, level: LogLevel, category: LogCategory) {
+        let mut st = self.segments.state.lock().unwrap();
+        let i = st.segments.partition_point(|s| s.last_seq < seq);
+        if let Some(segment) = st.segments.get_mut(i).filter(|s| s.first_seq <= seq) { segment.counts.bump(level, category); }
+    }
+
+    // Описание сегмента заменяется после перезаписи файла; ищется по пути — пока файл
+    // переписывался, в открытый сегмент ничего не добавлялось (это тот же поток)
+    fn replace(&self, path: &Path, segment: Option<Segment>) {
+        let mut st = self.segments.state.lock().unwrap();
+        let Some(i) = st.segments.iter().position(|s| s.path == path) else { return };
+        match segment {
+            Some(segment) => st.segments[i] = segment,
+            None => { st.segments.remove(i); }
+        }
+    }
+
+    // Дописывает итоговые счётчики повторов строками-поправками в сегменты с этими seq.
+    // Сегменты не переписываются: в каждый затронутый — одна дозапись.
+    pub fn amend(&mut self, repeats: &[(u64, Repeat)]) {
+        if self.segments.dir.is_none() || repeats.is_empty() { return; }
+        let mut by_path: HashMap<PathBuf, String> = HashMap::new();
+        {
+            let st = self.segments.state.lock().unwrap();
+            for (seq, repeat) in repeats {
+                let i = st.segments.partition_point(|s| s.last_seq < *seq);
+                let Some(segment) = st.segments.get(i).filter(|s| s.first_seq <= *seq) else { continue };
+                let mut line = serde_json::to_string(&Amend { amend: *seq, repeat: repeat.clone() }).expect("serializable amendment");
+                line.push('\n');
+                by_path.entry(segment.path.clone()).or_default().push_str(&line);
+            }
+        }
+        for (path, lines) in by_path {
+            let written = OpenOptions::new().append(true).open(&path).and_then(|mut file| file.write_all(lines.as_bytes()));
+            match written {
+                Ok(()) => {
+                    let mut st = self.segments.state.lock().unwrap();
+                    if let Some(segment) = st.segments.iter_mut().find(|s| s.path == path) {
+                        segment.bytes += lines.len() as u64;
+                        segment.save_meta();
+                    }
+                }
+                Err(e) => tracing::error!(path = %path.display(), error = %e, "Failed to amend log segment"),
+            }
+        }
+    }
+
+    // Сохраняет последний записанный seq, если он вырос с прошлого раза
+    fn save_floor(&self) {
+        let Some(dir) = &self.segments.dir else { return };
+        let (last, floor) = {
+            let st = self.segments.state.lock().unwrap();
+            (st.segments.last().map_or(0, |s| s.last_seq), st.floor)
+        };
+        if last <= floor { return; }
+        match fs::write(dir.join(SEQ_FILE), last.to_string()) {
+            Ok(()) => self.segments.state.lock().unwrap().floor = last,
+            Err(e) => tracing::error!(error = %e, "Failed to save log sequence number"),
+        }
+    }
+
+    // Удаляет и сжимает сегменты по срокам хранения, затем удаляет самые старые, пока
+    // общий размер не уложится в лимит. Самый новый сегмент не трогается, даже если он
+    // уже закрыт (например, после перезапуска): в нём продолжается нумерация.
+    pub fn enforce(&mut self, policy: &LogsConfig, now: DateTime<Utc>) -> Retained {
+        self.max_bytes = policy.segment_size_mb.max(1) as u64 * MB;
+        self.save_floor();
+        let mut report = Retained::default();
+        let mut segments = self.segments.snapshot();
+        if segments.last().is_some_and(|s| now - s.opened_at > MAX_SEGMENT_SPAN) { self.file = None; }
+        let sealed = segments.len().saturating_sub(1);
+
+        for segment in segments.iter_mut().take(sealed) {
+            let stale = segment.oldest.iter().any(|(cat, ts)| cutoff(policy, *cat, now).is_some_and(|c| *ts < c));
+            if !stale { continue; }
+            let (logs, dropped): (Vec<LogEntry>, Vec<LogEntry>) = segment.read().into_iter().partition(|l| !expired(policy, l, now));
+            report.dropped += dropped.len();
+            if logs.is_empty() {
+                remove(&segment.path);
+                self.replace(&segment.path, None);
+                report.deleted += 1;
+                segment.count = 0;
+                continue;
+            }
+            match rewrite(&segment.path, &logs) {
+                Ok(compacted) => {
+                    self.replace(&segment.path, Some(compacted.clone()));
+                    *segment = compacted;
+                    report.compacted += 1;
+                }
+                Err(e) => tracing::error!(path = %segment.path.display(), error = %e, "Failed to compact log segment"),
+            }
+        }
+        segments.retain(|s| s.count > 0);
+
+        let limit = policy.max_total_size_mb as u64 * MB;
+        let mut total: u64 = segments.iter().map(|s| s.bytes).sum();
+        let sealed = segments.len().saturating_sub(1);
+        let over: usize = if limit == 0 { 0 } else {
+            segments[..sealed].iter().take_while(|s| {
+                let over = total > limit;
+                if over { total -= s.bytes; }
+                over
+            }).count()
+        };
+        for segment in segments.drain(..over) {
+            remove(&segment.path);
+            self.replace(&segment.path, None);
+            report.deleted += 1;
+            report.dropped += segment.count;
+        }
+        report.oldest_seq = segments.first().map(|s| s.first_seq);
+        report
+    }
+}
+
+fn remove(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        tracing::error!(path = %path.display(), error = %e, "Failed to remove log segment");
+    }
+    let _ = fs::remove_file(path.with_extension(META_EXT));
+}
+
+// Сжатие: оставшиеся записи пишутся во временный файл, который атомарно заменяет сегмент
+fn rewrite(path: &Path, logs: &[LogEntry]) -> std::io::Result<Segment> {
+    let tmp = path.with_extension("tmp");
+    let mut segment = Segment::new(path.to_path_buf());
+    let mut body = String::new();
+    for log in logs {
+        let line = line(log);
+        segment.track(log, line.len() as u64);
+        body.push_str(&line);
+    }
+    fs::write(&tmp, body)?;
+    fs::rename(&tmp, path)?;
+    segment.save_meta();
+    Ok(segment)
+}
+
+// Каталог сегментов: DC_LOGS_DIR, по умолчанию рядом с базой. При DC_STORAGE=memory
+// журнал живёт только в памяти, если каталог не задан явно.
+pub fn dir_from_env() -> Option<PathBuf> {
+    match std::env::var("DC_LOGS_DIR") {
+        Ok(dir) => Some(dir.into()),
+        Err(_) if std::env::var("DC_STORAGE").as_deref() == Ok("memory") => None,
+        Err(_) => Some("deploy_center_logs".into()),
+    }
+}
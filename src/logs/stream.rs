@@ -0,0 +1,125 @@
+use std::{convert::Infallible, time::Duration};
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{sse::{Event, KeepAlive, Sse}, IntoResponse},
+};
+use async_stream::stream;
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+use super::{LogEntry, LogFilter, LogsStore};
+
+// Сколько записей досылается за один проход при возобновлении или отставании
+const REPLAY_CHUNK: usize = 500;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Resume {
+    // Для первого подключения EventSource, который не умеет ставить заголовки
+    #[serde(default)]
+    pub last_event_id: Option<u64>,
+}
+
+fn event(log: &LogEntry) -> Event {
+    Event::default().event("log").id(log.seq.to_string()).json_data(log).expect("serializable log entry")
+}
+
+// Новый счётчик уже отправленной записи; без id, чтобы не сдвигать Last-Event-ID
+fn repeat(log: &LogEntry) -> Event {
+    Event::default().event("repeat").json_data(log).expect("serializable log entry")
+}
+
+// Живой поток новых записей журнала (Server-Sent Events) с теми же фильтрами, что и список.
+// Клиент, переподключившийся с Last-Event-ID, получает всё пропущенное из хранилища.
+// Если клиент читает медленнее, чем пишутся логи, и отстаёт от канала, недостающие
+// записи тоже дочитываются из хранилища, так что поток не теряет записи.
+// Повторы схлопнутых записей приходят событием repeat с обновлённым счётчиком.
+pub async fn stream_logs(State(store): State<LogsStore>, headers: HeaderMap, Query(filter): Query<LogFilter>, Query(resume): Query<Resume>) -> impl IntoResponse {
+    let matcher = match filter.compile() {
+        Ok(m) => m,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let header_id = headers.get("last-event-id").and_then(|v| v.to_str().ok()).and_then(|v| v.trim().parse().ok());
+    let resume_from = header_id.or(resume.last_event_id);
+
+    // Подписка до чтения хранилища, чтобы не пропустить записи между ними
+    let mut rx = store.subscribe();
+    let mut last = match resume_from {
+        Some(seq) => seq,
+        None => store.last_seq().await,
+    };
+
+    let events = stream! {
+        loop {
+            let chunk = store.since(&matcher, last, REPLAY_CHUNK).await;
+            let Some(tail) = chunk.last() else { break };
+            last = tail.seq;
+            for log in &chunk { yield Ok::<_, Infallible>(event(log)); }
+        }
+        loop {
+            match rx.recv().await {
+                Ok(log) => {
+                    if log.seq <= last {
+                        if log.repeat.is_some() && matcher.matches(&log) { yield Ok(repeat(&log)); }
+                        continue;
+                    }
+                    last = log.seq;
+                    if matcher.matches(&log) { yield Ok(event(&log)); }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::debug!(skipped, "Log stream subscriber lagged, replaying from store");
+                    loop {
+                        let chunk = store.since(&matcher, last, REPLAY_CHUNK).await;
+                        let Some(tail) = chunk.last() else { break };
+                        last = tail.seq;
+                        for log in &chunk { yield Ok(event(log)); }
+                    }
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    };
+    Sse::new(events).keep_alive(KeepAlive::new().interval(Duration::from_secs(15))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{pin::Pin, sync::Arc};
+    use axum::body::{Body, HttpBody};
+    use super::*;
+    use crate::{logs::{LogCategory, LogLevel}, storage::{Db, MemoryStorage}};
+
+    // Читает поток, пока не придёт n событий log; возвращает их id
+    async fn read_ids(body: &mut Body, n: usize) -> Vec<u64> {
+        let mut text = String::new();
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while text.matches("event: log").count() < n {
+                let frame = std::future::poll_fn(|cx| Pin::new(&mut *body).poll_frame(cx)).await.unwrap().unwrap();
+                if let Ok(data) = frame.into_data() { text.push_str(std::str::from_utf8(&data).unwrap()); }
+            }
+        }).await.expect("stream stalled");
+        text.lines().filter_map(|l| l.strip_prefix("id: ")).map(|id| id.parse().unwrap()).collect()
+    }
+
+    fn entry(category: LogCategory) -> LogEntry {
+        LogEntry::new(LogLevel::Info, category, "event", "test")
+    }
+
+    #[tokio::test]
+    async fn resumes_after_last_event_id_then_goes_live() {
+        let db: Db = Arc::new(MemoryStorage::default());
+        let store = LogsStore::open_in(&db, None);
+        let stored = store.append_many(vec![entry(LogCategory::Api), entry(LogCategory::Api), entry(LogCategory::System), entry(LogCategory::Api)]).await;
+        let seqs: Vec<u64> = stored.iter().map(|l| l.seq).collect();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("last-event-id", seqs[0].to_string().parse().unwrap());
+        let filter = LogFilter { category: vec![LogCategory::Api], ..LogFilter::default() };
+        let res = stream_logs(State(store.clone()), headers, Query(filter), Query(Resume::default())).await.into_response();
+        let mut body = res.into_body();
+        // Пропущенное досылается из хранилища без записей других категорий
+        assert_eq!(read_ids(&mut body, 2).await, [seqs[1], seqs[3]]);
+
+        let live = store.append(entry(LogCategory::Api)).await.unwrap();
+        assert_eq!(read_ids(&mut body, 1).await, [live.seq]);
+    }
+}
@@ -0,0 +1,377 @@
+use std::{net::{IpAddr, SocketAddr}, sync::Arc};
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, Utc};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader},
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::Semaphore,
+    time::timeout,
+};
+use super::{ingest::{MAX_FIELD_BYTES, MAX_MESSAGE_BYTES}, truncate, LogCategory, LogEntry, LogLevel, LogsStore, MAX_CLOCK_SKEW};
+use crate::{ip_filter, settings::SettingsStore};
+
+// Больше этого сообщение не бывает ни в UDP, ни при разумной отправке по TCP
+const MAX_FRAME: usize = 64 * 1024;
+// Длина кадра с пробелом после неё: для MAX_FRAME хватает шести байт
+const MAX_LEN_PREFIX: u64 = 10;
+// Соединение без единого сообщения за это время закрывается
+const IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+const MAX_CONNECTIONS: usize = 256;
+
+// Разобранное сообщение RFC 5424 или RFC 3164
+#[derive(Debug, Default, PartialEq)]
+pub struct SyslogMessage {
+    pub facility: u8,
+    pub severity: u8,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub hostname: Option<String>,
+    pub app_name: Option<String>,
+    pub procid: Option<String>,
+    pub msgid: Option<String>,
+    pub structured_data: Option<String>,
+    pub message: String,
+}
+
+fn nil(value: &str) -> Option<String> { (value != "-" && !value.is_empty()).then(|| value.to_string()) }
+
+// Без PRI сообщение считается user.notice, как предписывает RFC 3164
+fn split_pri(raw: &str) -> (u8, u8, &str) {
+    if let Some(rest) = raw.strip_prefix('<')
+        && let Some((pri, rest)) = rest.split_once('>')
+        && let Ok(pri) = pri.parse::<u8>()
+        && pri < 192
+    {
+        return (pri / 8, pri % 8, rest);
+    }
+    (1, 5, raw)
+}
+
+pub fn parse(raw: &str) -> SyslogMessage {
+    let raw = raw.trim_end_matches(['\r', '\n', '\0']);
+    let (facility, severity, rest) = split_pri(raw);
+    let mut msg = match rest.strip_prefix("1 ") {
+        Some(rest) => parse_5424(rest),
+        None => parse_3164(rest),
+    };
+    msg.facility = facility;
+    msg.severity = severity;
+    msg
+}
+
+// TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA [MSG]
+fn parse_5424(rest: &str) -> SyslogMessage {
+    let mut parts = rest.splitn(6, ' ');
+    let mut field = || parts.next().unwrap_or("-");
+    let timestamp = DateTime::parse_from_rfc3339(field()).ok().map(|t| t.with_timezone(&Utc));
+    let (hostname, app_name, procid, msgid) = (nil(field()), nil(field()), nil(field()), nil(field()));
+    let rest = parts.next().unwrap_or_default();
+    let (structured_data, message) = split_structured_data(rest);
+    SyslogMessage {
+        timestamp,
+        hostname,
+        app_name,
+        procid,
+        msgid,
+        structured_data,
+        message: message.trim_start_matches('\u{feff}').to_string(),
+        ..Default::default()
+    }
+}
+
+// Структурированные данные — "-" или подряд идущие [..] с экранированием \" \\ \]
+fn split_structured_data(rest: &str) -> (Option<String>, &str) {
+    if let Some(message) = rest.strip_prefix('-') { return (None, message.strip_prefix(' ').unwrap_or(message)); }
+    let (mut depth, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in rest.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' if depth > 0 => quoted = !quoted,
+            '[' if !quoted => depth += 1,
+            ']' if !quoted => {
+                depth -= 1;
+                if depth == 0 && !rest[i + 1..].starts_with('[') {
+                    let message = &rest[i + 1..];
+                    return (Some(rest[..=i].to_string()), message.strip_prefix(' ').unwrap_or(message));
+                }
+            }
+            _ if depth == 0 => break,
+            _ => {}
+        }
+    }
+    (None, rest)
+}
+
+// "Oct 18 03:57:17 host tag[pid]: message". Год и пояс не передаются: берутся текущий год и UTC.
+fn parse_3164(rest: &str) -> SyslogMessage {
+    let now = Utc::now();
+    let timestamp = rest.get(..15).and_then(|ts| {
+        let ts = NaiveDateTime::parse_from_str(&format!("{} {}", now.year(), ts), "%Y %b %e %H:%M:%S").ok()?.and_utc();
+        // Декабрьское сообщение, полученное в январе, относится к прошлому году
+        Some(if ts > now + Duration::days(1) { ts.with_year(now.year() - 1).unwrap_or(ts) } else { ts })
+    });
+    let Some(timestamp) = timestamp else {
+        return SyslogMessage { message: rest.to_string(), ..Default::default() };
+    };
+    let rest = rest[15..].trim_start();
+    let (hostname, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+    // TAG — до '[', ':' или пробела; дальше необязательный [pid] и ": "
+    let tag_end = rest.find(['[', ':', ' ']).unwrap_or(rest.len());
+    let (app_name, mut rest) = rest.split_at(tag_end);
+    let mut procid = None;
+    if let Some(after) = rest.strip_prefix('[') && let Some((pid, after)) = after.split_once(']') {
+        procid = nil(pid);
+        rest = after;
+    }
+    let message = rest.strip_prefix(':').unwrap_or(rest).trim_start();
+    SyslogMessage {
+        timestamp: Some(timestamp),
+        hostname: nil(hostname),
+        app_name: nil(app_name),
+        procid,
+        message: message.to_string(),
+        ..Default::default()
+    }
+}
+
+fn level(severity: u8) -> LogLevel {
+    match severity {
+        0..=3 => LogLevel::Error,
+        4 => LogLevel::Warning,
+        5 | 6 => LogLevel::Info,
+        _ => LogLevel::Debug,
+    }
+}
+
+// Обратное отображению при экспорте: auth/authpriv/audit — безопасность, local0..local3 — остальные категории
+fn category(facility: u8) -> LogCategory {
+    match facility {
+        4 | 10 | 13 => LogCategory::Security,
+        1 => LogCategory::User,
+        16 => LogCategory::Database,
+        17 => LogCategory::Api,
+        18 => LogCategory::User,
+        19 => LogCategory::Network,
+        _ => LogCategory::System,
+    }
+}
+
+impl SyslogMessage {
+    // Запись журнала; ip — адрес отправителя. MSGID, совпадающий с категорией, важнее facility.
+    pub fn into_entry(self, ip: IpAddr) -> Option<LogEntry> {
+        let message = truncate(self.message.trim(), MAX_MESSAGE_BYTES);
+        if message.is_empty() { return None; }
+        let category = self.msgid.as_deref()
+            .and_then(LogCategory::parse)
+            .unwrap_or_else(|| category(self.facility));
+        let source = self.app_name.as_deref().or(self.hostname.as_deref()).unwrap_or("syslog");
+        let mut entry = LogEntry::new(level(self.severity), category, message, truncate(source, MAX_FIELD_BYTES));
+        let now = Utc::now();
+        entry.timestamp = self.timestamp.filter(|ts| *ts <= now + MAX_CLOCK_SKEW).unwrap_or(now);
+        entry.ip = Some(ip.to_string());
+        let details: Vec<String> = [("host", self.hostname), ("procid", self.procid), ("msgid", self.msgid), ("sd", self.structured_data)]
+            .into_iter()
+            .filter_map(|(name, value)| Some(format!("{}={}", name, value?)))
+            .collect();
+        entry.details = (!details.is_empty()).then(|| details.join("\n"));
+        Some(entry)
+    }
+}
+
+// Приём от отправителя с учётом списка разрешённых IP и конвейера разбора из настроек
+async fn accept(store: &LogsStore, settings: &SettingsStore, peer: IpAddr, raw: &[u8]) {
+    let pipeline = {
+        let cfg = settings.inner.read().await;
+        if !ip_filter::is_allowed(&cfg.security, peer) { return; }
+        cfg.logs.syslog_pipeline.clone()
+    };
+    let raw = String::from_utf8_lossy(raw);
+    let entry = parse(&raw).into_entry(peer).and_then(|e| store.pipelines().apply(pipeline.as_deref(), e));
+    if let Some(entry) = entry { store.append(entry).await; }
+}
+
+async fn serve_udp(socket: UdpSocket, store: LogsStore, settings: SettingsStore) {
+    let mut buf = vec![0u8; MAX_FRAME];
+    loop {
+        match socket.recv_from(&mut buf).await {
+            Ok((len, peer)) => accept(&store, &settings, peer.ip(), &buf[..len]).await,
+            Err(e) => tracing::warn!(error = %e, "Syslog UDP receive failed"),
+        }
+    }
+}
+
+// Следующий кадр TCP по RFC 6587: подсчёт октетов ("123 <34>1 ...") или по сообщению на строку.
+// false — соединение пора закрыть: конец потока, ошибка или нарушение формата.
+async fn read_frame(reader: &mut (impl AsyncBufRead + Unpin), frame: &mut Vec<u8>) -> bool {
+    frame.clear();
+    let first = match reader.fill_buf().await {
+        Ok([]) | Err(_) => return false,
+        Ok(buf) => buf[0],
+    };
+    if first.is_ascii_digit() {
+        let mut len = Vec::new();
+        if (&mut *reader).take(MAX_LEN_PREFIX).read_until(b' ', &mut len).await.is_err() { return false; }
+        let Some(digits) = len.strip_suffix(b" ").filter(|d| d.iter().all(u8::is_ascii_digit)) else { return false };
+        let Some(len) = std::str::from_utf8(digits).ok().and_then(|l| l.parse::<usize>().ok()).filter(|l| *l <= MAX_FRAME) else { return false };
+        frame.resize(len, 0);
+        return reader.read_exact(frame).await.is_ok();
+    }
+    match (&mut *reader).take(MAX_FRAME as u64).read_until(b'\n', frame).await {
+        Ok(0) | Err(_) => false,
+        Ok(_) => frame.ends_with(b"\n") || frame.len() < MAX_FRAME,
+    }
+}
+
+async fn serve_tcp_conn(stream: TcpStream, peer: SocketAddr, store: LogsStore, settings: SettingsStore) {
+    let mut reader = BufReader::new(stream);
+    let mut frame = Vec::new();
+    while let Ok(true) = timeout(IDLE_TIMEOUT, read_frame(&mut reader, &mut frame)).await {
+        accept(&store, &settings, peer.ip(), &frame).await;
+    }
+}
+
+async fn serve_tcp(listener: TcpListener, store: LogsStore, settings: SettingsStore) {
+    // Сверх MAX_CONNECTIONS новые соединения ждут в очереди ядра, пока не закроется одно из открытых
+    let slots = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    loop {
+        let slot = slots.clone().acquire_owned().await.expect("syslog connection semaphore closed");
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let (store, settings) = (store.clone(), settings.clone());
+                tokio::spawn(async move {
+                    serve_tcp_conn(stream, peer, store, settings).await;
+                    drop(slot);
+                });
+            }
+            Err(e) => tracing::warn!(error = %e, "Syslog TCP accept failed"),
+        }
+    }
+}
+
+// Приёмник включается адресами DC_SYSLOG_UDP и/или DC_SYSLOG_TCP, например 0.0.0.0:5514
+pub async fn spawn(store: LogsStore, settings: SettingsStore) {
+    if let Ok(addr) = std::env::var("DC_SYSLOG_UDP") {
+        let socket = UdpSocket::bind(&addr).await.expect("failed to bind syslog UDP socket");
+        tracing::info!(%addr, "Syslog UDP receiver started");
+        tokio::spawn(serve_udp(socket, store.clone(), settings.clone()));
+    }
+    if let Ok(addr) = std::env::var("DC_SYSLOG_TCP") {
+        let listener = TcpListener::bind(&addr).await.expect("failed to bind syslog TCP listener");
+        tracing::info!(%addr, "Syslog TCP receiver started");
+        tokio::spawn(serve_tcp(listener, store, settings));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn frames(mut input: &[u8]) -> (Vec<String>, bool) {
+        let mut frames = Vec::new();
+        let mut frame = Vec::new();
+        while read_frame(&mut input, &mut frame).await { frames.push(String::from_utf8_lossy(&frame).into_owned()); }
+        (frames, input.is_empty())
+    }
+
+    #[tokio::test]
+    async fn reads_octet_counted_and_line_frames() {
+        let (frames, done) = frames(b"11 <34>1 hello12 <34>1 world!<13>plain line\n").await;
+        assert_eq!(frames, ["<34>1 hello", "<34>1 world!", "<13>plain line\n"]);
+        assert!(done);
+    }
+
+    #[tokio::test]
+    async fn rejects_unbounded_or_invalid_length_prefix() {
+        // Цифры без пробела: читается не больше MAX_LEN_PREFIX байт, и соединение закрывается
+        let digits = vec![b'7'; 1 << 20];
+        let (read, done) = frames(&digits).await;
+        assert!(read.is_empty() && !done);
+        assert!(frames(b"12x4 <34>1 hi").await.0.is_empty());
+        assert!(frames(b"99999999 <34>1 hi").await.0.is_empty());
+    }
+
+    fn localhost() -> IpAddr { IpAddr::from([127, 0, 0, 1]) }
+
+    #[test]
+    fn parses_rfc_3164_without_year() {
+        let msg = parse("<34>Oct 11 22:14:15 mymachine su: 'su root' failed for lonvick on /dev/pts/8");
+        assert_eq!((msg.facility, msg.severity), (4, 2));
+        assert_eq!((msg.hostname.as_deref(), msg.app_name.as_deref(), msg.procid.as_deref()), (Some("mymachine"), Some("su"), None));
+        assert_eq!(msg.message, "'su root' failed for lonvick on /dev/pts/8");
+        // Год — текущий, если дата не оказывается в будущем, иначе прошлый
+        let now = Utc::now();
+        let ts = msg.timestamp.unwrap();
+        assert_eq!(ts.format("%m-%d %H:%M:%S").to_string(), "10-11 22:14:15");
+        assert!(ts <= now + Duration::days(1) && ts.year() >= now.year() - 1);
+
+        let msg = parse("<13>Feb  5 17:32:18 10.0.0.99 sshd[1234]: Accepted publickey\n");
+        assert_eq!((msg.app_name.as_deref(), msg.procid.as_deref()), (Some("sshd"), Some("1234")));
+        assert_eq!(msg.timestamp.unwrap().format("%m-%d").to_string(), "02-05");
+        assert_eq!(msg.message, "Accepted publickey");
+    }
+
+    #[test]
+    fn parses_rfc_5424_structured_data() {
+        let msg = parse("<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut=\"3\" eventSource=\"Application\"][examplePriority@32473 class=\"high\"] \u{feff}An application event");
+        assert_eq!((msg.facility, msg.severity), (20, 5));
+        assert_eq!(msg.timestamp.unwrap().to_rfc3339(), "2003-10-11T22:14:15.003+00:00");
+        assert_eq!((msg.hostname.as_deref(), msg.app_name.as_deref()), (Some("mymachine.example.com"), Some("evntslog")));
+        assert_eq!((msg.procid, msg.msgid.as_deref()), (None, Some("ID47")));
+        assert_eq!(msg.structured_data.as_deref(), Some("[exampleSDID@32473 iut=\"3\" eventSource=\"Application\"][examplePriority@32473 class=\"high\"]"));
+        assert_eq!(msg.message, "An application event");
+
+        // Экранированная скобка внутри значения не закрывает элемент
+        let msg = parse(r#"<14>1 2024-01-01T00:00:00Z host app 1 - [a@1 x="q\]z"] done"#);
+        assert_eq!(msg.structured_data.as_deref(), Some(r#"[a@1 x="q\]z"]"#));
+        assert_eq!(msg.message, "done");
+    }
+
+    #[test]
+    fn parses_rfc_5424_nil_values() {
+        let msg = parse("<34>1 - - - - - -");
+        assert_eq!(msg, SyslogMessage { facility: 4, severity: 2, ..Default::default() });
+        let msg = parse("<34>1 - - - - - - only message");
+        assert_eq!((msg.timestamp, msg.structured_data, msg.message.as_str()), (None, None, "only message"));
+    }
+
+    #[test]
+    fn maps_priority_to_level_and_category() {
+        let entry = |raw: &str| parse(raw).into_entry(localhost()).unwrap();
+        let cases = [
+            ("<11>1 - - - - - - m", LogLevel::Error, LogCategory::User),
+            ("<84>1 - - - - - - m", LogLevel::Warning, LogCategory::Security),
+            ("<86>1 - - - - - - m", LogLevel::Info, LogCategory::Security),
+            ("<135>1 - - - - - - m", LogLevel::Debug, LogCategory::Database),
+            ("<141>1 - - - - - - m", LogLevel::Info, LogCategory::Api),
+            ("<24>1 - - - - - - m", LogLevel::Error, LogCategory::System),
+            // MSGID с именем категории важнее facility
+            ("<24>1 - - - - Network - m", LogLevel::Error, LogCategory::Network),
+        ];
+        for (raw, level, category) in cases {
+            let e = entry(raw);
+            assert_eq!((e.level, e.category), (level, category), "{}", raw);
+        }
+        let e = entry("<14>1 2003-10-11T22:14:15Z web nginx 42 ID1 - hello");
+        assert_eq!((e.source.as_str(), e.ip.as_deref()), ("nginx", Some("127.0.0.1")));
+        assert_eq!(e.details.as_deref(), Some("host=web\nprocid=42\nmsgid=ID1"));
+    }
+
+    #[test]
+    fn tolerates_malformed_input() {
+        // Неверный PRI — сообщение целиком, user.notice
+        let msg = parse("<999>hello");
+        assert_eq!((msg.facility, msg.severity, msg.message.as_str()), (1, 5, "<999>hello"));
+        assert_eq!(parse("<12").message, "<12");
+        // Без даты RFC 3164 заголовок не разбирается
+        let msg = parse("<13>not a date at all");
+        assert_eq!((msg.timestamp, msg.hostname, msg.message.as_str()), (None, None, "not a date at all"));
+        let msg = parse("<13>1 yesterday host app - - - text");
+        assert_eq!((msg.timestamp, msg.message.as_str()), (None, "text"));
+        // Незакрытые структурированные данные остаются в сообщении
+        assert_eq!(parse("<13>1 - - - - - [a@1 x=\"y\" text").message, "[a@1 x=\"y\" text");
+        assert!(parse("").into_entry(localhost()).is_none());
+        assert!(parse("<13>1 - - - - - -   ").into_entry(localhost()).is_none());
+        // Время из будущего заменяется временем приёма
+        let e = parse("<13>1 2999-01-01T00:00:00Z - - - - - later").into_entry(localhost()).unwrap();
+        assert!(e.timestamp <= Utc::now());
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use super::{ingest::MAX_MESSAGE_BYTES, truncate, LogCategory, LogEntry, LogLevel, LogsStore, Pipelines};
+use crate::{settings::SettingsStore, storage::{Collection, Db}};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Сколько читается из одного файла за проход; остальное — на следующем
+const MAX_READ: u64 = 1024 * 1024;
+// Строка без перевода строки длиннее этого всё равно выдаётся записью
+const MAX_LINE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelPattern { pub pattern: String, pub level: LogLevel }
+
+// Файл или glob, за которым сервер следит как tail -F
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TailSource {
+    pub path: String,
+    #[serde(default = "default_category")]
+    pub category: LogCategory,
+    // По умолчанию — имя файла
+    #[serde(default)]
+    pub source: Option<String>,
+    // Проверяются по порядку, первое совпадение задаёт уровень; пустой список — шаблоны по умолчанию
+    #[serde(default)]
+    pub level_patterns: Vec<LevelPattern>,
+    // Конвейер разбора строк; уровень из него важнее level_patterns
+    #[serde(default)]
+    pub pipeline: Option<String>,
+}
+
+fn default_category() -> LogCategory { LogCategory::System }
+
+fn default_patterns() -> Vec<LevelPattern> {
+    [
+        (r"\b(fatal|panic|crit(ical)?|err(or)?)\b", LogLevel::Error),
+        (r"\bwarn(ing)?\b", LogLevel::Warning),
+        (r"\b(debug|trace)\b", LogLevel::Debug),
+    ].into_iter().map(|(pattern, level)| LevelPattern { pattern: pattern.into(), level }).collect()
+}
+
+struct Compiled { source: TailSource, pattern: glob::Pattern, levels: Vec<(Regex, LogLevel)> }
+
+impl Compiled {
+    fn new(source: &TailSource) -> Result<Self, String> {
+        let pattern = glob::Pattern::new(&source.path).map_err(|e| format!("invalid path pattern {:?}: {}", source.path, e))?;
+        let patterns = if source.level_patterns.is_empty() { default_patterns() } else { source.level_patterns.clone() };
+        let levels = patterns.iter()
+            .map(|p| RegexBuilder::new(&p.pattern).case_insensitive(true).build().map(|re| (re, p.level)).map_err(|e| format!("invalid level pattern {:?}: {}", p.pattern, e)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { source: source.clone(), pattern, levels })
+    }
+
+    fn level(&self, line: &str) -> LogLevel {
+        self.levels.iter().find(|(re, _)| re.is_match(line)).map(|(_, level)| *level).unwrap_or(LogLevel::Info)
+    }
+
+    fn entry(&self, pipelines: &Pipelines, path: &Path, line: &str) -> Option<LogEntry> {
+        let source = self.source.source.clone()
+            .unwrap_or_else(|| path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default());
+        let mut entry = LogEntry::new(self.level(line), self.source.category, truncate(line, MAX_MESSAGE_BYTES), source);
+        entry.details = Some(path.display().to_string());
+        pipelines.apply(self.source.pipeline.as_deref(), entry)
+    }
+}
+
+pub fn validate(sources: &[TailSource]) -> Result<(), String> {
+    sources.iter().try_for_each(|s| Compiled::new(s).map(|_| ()))
+}
+
+// Сохранённая позиция: по dev/inode видно, тот ли это файл после перезапуска
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TailOffset { path: String, dev: u64, ino: u64, offset: u64 }
+
+struct Tailed { file: File, dev: u64, ino: u64, offset: u64, pending: Vec<u8>, source: usize }
+
+impl Tailed {
+    // Новые полные строки с текущей позиции
+    fn read_lines(&mut self) -> Vec<String> {
+        let mut buf = Vec::new();
+        if self.file.seek(SeekFrom::Start(self.offset)).is_err() { return Vec::new(); }
+        let read = (&mut self.file).take(MAX_READ).read_to_end(&mut buf).unwrap_or(0);
+        self.offset += read as u64;
+        self.pending.extend_from_slice(&buf);
+        let mut lines = Vec::new();
+        while let Some(pos) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=pos).collect();
+            lines.push(String::from_utf8_lossy(&line).trim_end().to_string());
+        }
+        if self.pending.len() > MAX_LINE {
+            lines.push(String::from_utf8_lossy(&std::mem::take(&mut self.pending)).into_owned());
+        }
+        lines.retain(|l| !l.trim().is_empty());
+        lines
+    }
+
+    // Позиция начала ещё не разобранного остатка строки — её и нужно запомнить
+    fn saved(&self, path: &Path) -> TailOffset {
+        TailOffset { path: path.display().to_string(), dev: self.dev, ino: self.ino, offset: self.offset - self.pending.len() as u64 }
+    }
+}
+
+struct Tailer {
+    sources: Vec<Compiled>,
+    files: HashMap<PathBuf, Tailed>,
+    saved: HashMap<String, TailOffset>,
+    // Шаблоны, уже просмотренные хотя бы раз: файлы, найденные позже, читаются с начала
+    seen: HashSet<String>,
+    db: Collection,
+    pipelines: Pipelines,
+}
+
+impl Tailer {
+    fn open(db: &Db, pipelines: Pipelines) -> Self {
+        let db = Collection::new(db, "tail_offsets");
+        let saved = db.load_or_seed(|o: &TailOffset| o.path.clone(), Vec::new).into_iter().map(|o| (o.path.clone(), o)).collect();
+        Self { sources: Vec::new(), files: HashMap::new(), saved, seen: HashSet::new(), db, pipelines }
+    }
+
+    fn configure(&mut self, sources: &[TailSource]) {
+        if self.sources.iter().map(|c| &c.source).eq(sources.iter()) { return; }
+        self.sources = sources.iter().filter_map(|s| Compiled::new(s).map_err(|e| tracing::error!(error = %e, "Skipping tail source")).ok()).collect();
+        self.seen.retain(|p| sources.iter().any(|s| &s.path == p));
+        // Индексы источников поменялись — файлы откроются заново с сохранённых позиций
+        self.files.clear();
+    }
+
+    fn poll(&mut self) -> Vec<LogEntry> {
+        let mut entries = Vec::new();
+        let mut live = HashSet::new();
+        // Позиции файлов, отцепленных от своего пути ротацией в этом проходе
+        let mut rotated: HashMap<(u64, u64), u64> = HashMap::new();
+        for (index, compiled) in self.sources.iter().enumerate() {
+            // Шаблон с сохранёнными позициями уже просматривался до перезапуска
+            let first_scan = self.seen.insert(compiled.source.path.clone()) && !self.saved.keys().any(|k| compiled.pattern.matches(k));
+            let Ok(paths) = glob::glob(&compiled.source.path) else { continue };
+            for path in paths.filter_map(Result::ok) {
+                let Ok(meta) = std::fs::metadata(&path) else { continue };
+                if !meta.is_file() || !live.insert(path.clone()) { continue; }
+                let (dev, ino) = (meta.dev(), meta.ino());
+                if let Some(tailed) = self.files.get_mut(&path) {
+                    if (tailed.dev, tailed.ino) == (dev, ino) {
+                        // Усечение (copytruncate или > file): читаем заново с начала
+                        if meta.len() < tailed.offset { tailed.offset = 0; tailed.pending.clear(); }
+                        entries.extend(tailed.read_lines().iter().filter_map(|l| compiled.entry(&self.pipelines, &path, l)));
+                        continue;
+                    }
+                    // Ротация: дочитываем старый файл, затем переходим на новый с начала
+                    let mut old = self.files.remove(&path).expect("tracked file");
+                    entries.extend(old.read_lines().iter().filter_map(|l| compiled.entry(&self.pipelines, &path, l)));
+                    rotated.insert((old.dev, old.ino), old.offset);
+                }
+                // Переименованный файл (app.log -> app.log.1) продолжает читаться с прежней позиции
+                let renamed = self.files.iter().find(|(_, t)| (t.dev, t.ino) == (dev, ino)).map(|(p, _)| p.clone());
+                if let Some(mut tailed) = renamed.and_then(|p| self.files.remove(&p)) {
+                    tailed.source = index;
+                    entries.extend(tailed.read_lines().iter().filter_map(|l| compiled.entry(&self.pipelines, &path, l)));
+                    self.files.insert(path, tailed);
+                    continue;
+                }
+                let Ok(file) = File::open(&path) else { continue };
+                let known = rotated.get(&(dev, ino)).copied()
+                    .or_else(|| self.saved.values().find(|s| (s.dev, s.ino) == (dev, ino) && s.offset <= meta.len()).map(|s| s.offset));
+                let offset = match known {
+                    Some(offset) => offset,
+                    // Уже существующие файлы при первом просмотре шаблона не вычитываются целиком
+                    None if first_scan && !self.saved.contains_key(&path.display().to_string()) => meta.len(),
+                    None => 0,
+                };
+                let mut tailed = Tailed { file, dev, ino, offset, pending: Vec::new(), source: index };
+                entries.extend(tailed.read_lines().iter().filter_map(|l| compiled.entry(&self.pipelines, &path, l)));
+                self.files.insert(path, tailed);
+            }
+        }
+        // Удалённые файлы дочитываются через открытый дескриптор и забываются
+        let gone: Vec<PathBuf> = self.files.keys().filter(|p| !live.contains(*p)).cloned().collect();
+        for path in gone {
+            let mut tailed = self.files.remove(&path).expect("tracked file");
+            if let Some(compiled) = self.sources.get(tailed.source) {
+                entries.extend(tailed.read_lines().iter().filter_map(|l| compiled.entry(&self.pipelines, &path, l)));
+            }
+        }
+        entries
+    }
+
+    // Вызывается после сохранения прочитанных записей: иначе при остановке между
+    // ними строки были бы потеряны, а так — только прочитаны повторно
+    fn persist(&mut self) {
+        for (path, tailed) in &self.files {
+            let offset = tailed.saved(path);
+            if self.saved.get(&offset.path) != Some(&offset) {
+                self.db.put(&offset.path, &offset);
+                self.saved.insert(offset.path.clone(), offset);
+            }
+        }
+        let stale: Vec<String> = self.saved.keys().filter(|k| !self.files.contains_key(Path::new(k.as_str()))).cloned().collect();
+        for key in stale {
+            self.db.remove(&key);
+            self.saved.remove(&key);
+        }
+    }
+}
+
+// Опрос файлов из настроек раз в секунду; чтение идёт в блокирующем пуле
+pub fn spawn(db: &Db, store: LogsStore, settings: SettingsStore) {
+    let mut tailer = Tailer::open(db, store.pipelines().clone());
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            tick.tick().await;
+            let sources = settings.inner.read().await.logs.files.clone();
+            let (back, entries) = tokio::task::spawn_blocking(move || {
+                tailer.configure(&sources);
+                let entries = tailer.poll();
+                (tailer, entries)
+            }).await.expect("log tail task panicked");
+            tailer = back;
+            if !entries.is_empty() { store.append_many(entries).await; }
+            tailer = tokio::task::spawn_blocking(move || { tailer.persist(); tailer }).await.expect("log tail task panicked");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn offsets_are_saved_only_after_persist() {
+        let dir = std::env::temp_dir().join(format!("dc_tail_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        std::fs::write(&path, "").unwrap();
+        let db: Db = Arc::new(MemoryStorage::default());
+        let pipelines = Pipelines::open(&db);
+        let source = TailSource { path: path.display().to_string(), category: LogCategory::System, source: None, level_patterns: Vec::new(), pipeline: None };
+        let mut tailer = Tailer::open(&db, pipelines.clone());
+        tailer.configure(std::slice::from_ref(&source));
+        assert!(tailer.poll().is_empty());
+        std::fs::write(&path, "error: disk full\n").unwrap();
+        let entries = tailer.poll();
+        assert_eq!(entries.iter().map(|e| e.level).collect::<Vec<_>>(), [LogLevel::Error]);
+
+        // Записи прочитаны, но ещё не сохранены — позиция на диске прежняя
+        let saved = |db: &Db| Tailer::open(db, pipelines.clone()).saved.values().map(|o| o.offset).next();
+        assert_eq!(saved(&db), None);
+        tailer.persist();
+        assert_eq!(saved(&db), Some(17));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,132 @@
+use std::sync::Arc;
+use axum::{extract::{Path, State}, http::{Method, StatusCode}, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+use super::LogsStore;
+use crate::{auth::{random_token, token_id, CurrentUser}, storage::{Collection, Db}};
+
+// По префиксу require_auth отличает токен приёма от токена сессии
+pub const TOKEN_PREFIX: &str = "dci_";
+const MAX_NAME: usize = 100;
+
+// Токен приёма журнала для внешних сервисов. Открывает только POST /logs и
+// /logs/batch, без пользователя и его прав. Сам токен не хранится — только sha256.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestToken {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    hash: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    // Последний запрос с токеном — только в памяти, как у сессий
+    #[serde(skip_deserializing)]
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+impl IngestToken {
+    // Для ответов API: без хеша
+    fn public(&self) -> Self { Self { hash: String::new(), ..self.clone() } }
+}
+
+// Сервис, отправивший запрос с токеном приёма; кладётся в запрос middleware `require_auth`
+#[derive(Debug, Clone)]
+pub struct IngestClient(pub IngestToken);
+
+// Маршруты внутри /api, куда пускает токен приёма
+pub fn accepts_ingest_token(method: &Method, route: &str) -> bool {
+    method == Method::POST && matches!(route, "/logs" | "/logs/batch")
+}
+
+#[derive(Debug, Clone)]
+pub struct IngestTokens { items: Arc<RwLock<Vec<IngestToken>>>, db: Collection }
+
+impl IngestTokens {
+    pub fn open(db: &Db) -> Self {
+        let db = Collection::new(db, "ingest_tokens");
+        let items = db.load_or_seed(|t: &IngestToken| t.id.to_string(), Vec::new);
+        Self { items: Arc::new(RwLock::new(items)), db }
+    }
+
+    // Возвращает токен для сервиса; показывается один раз
+    pub async fn create(&self, name: &str, created_by: &str) -> (String, IngestToken) {
+        let token = format!("{}{}", TOKEN_PREFIX, random_token(32));
+        let item = IngestToken {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            hash: token_id(&token),
+            created_by: created_by.to_string(),
+            created_at: Utc::now(),
+            last_used: None,
+        };
+        self.db.put(&item.id.to_string(), &item);
+        self.items.write().await.push(item.clone());
+        (token, item.public())
+    }
+
+    pub async fn lookup(&self, token: &str) -> Option<IngestToken> {
+        let hash = token_id(token);
+        let mut items = self.items.write().await;
+        let found = items.iter_mut().find(|t| t.hash == hash)?;
+        found.last_used = Some(Utc::now());
+        Some(found.public())
+    }
+
+    pub async fn list(&self) -> Vec<IngestToken> {
+        self.items.read().await.iter().map(IngestToken::public).collect()
+    }
+
+    pub async fn revoke(&self, id: Uuid) -> bool {
+        let mut items = self.items.write().await;
+        let Some(pos) = items.iter().position(|t| t.id == id) else { return false };
+        items.remove(pos);
+        self.db.remove(&id.to_string());
+        true
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateToken { pub name: String }
+
+#[derive(Debug, Serialize)]
+pub struct CreatedToken { pub token: String, #[serde(flatten)] pub info: IngestToken }
+
+pub async fn list_tokens(State(store): State<LogsStore>) -> Json<Vec<IngestToken>> {
+    Json(store.tokens().list().await)
+}
+
+pub async fn create_token(State(store): State<LogsStore>, CurrentUser(user): CurrentUser, Json(payload): Json<CreateToken>) -> impl IntoResponse {
+    let name = payload.name.trim();
+    if name.is_empty() { return (StatusCode::UNPROCESSABLE_ENTITY, "name must not be empty".to_string()).into_response(); }
+    if name.len() > MAX_NAME { return (StatusCode::UNPROCESSABLE_ENTITY, format!("name exceeds {} bytes", MAX_NAME)).into_response(); }
+    let (token, info) = store.tokens().create(name, &user.username).await;
+    (StatusCode::CREATED, Json(CreatedToken { token, info })).into_response()
+}
+
+pub async fn delete_token(State(store): State<LogsStore>, Path(id): Path<Uuid>) -> impl IntoResponse {
+    if store.tokens().revoke(id).await { StatusCode::NO_CONTENT } else { StatusCode::NOT_FOUND }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[tokio::test]
+    async fn tokens_are_stored_hashed_and_survive_restart() {
+        let db: Db = Arc::new(MemoryStorage::default());
+        let tokens = IngestTokens::open(&db);
+        let (token, info) = tokens.create("billing", "admin").await;
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert!(info.hash.is_empty());
+        assert!(!serde_json::to_string(&info).unwrap().contains("hash"));
+
+        let reopened = IngestTokens::open(&db);
+        assert_eq!(reopened.lookup(&token).await.map(|t| t.id), Some(info.id));
+        assert!(reopened.lookup("dci_unknown").await.is_none());
+        assert!(reopened.revoke(info.id).await);
+        assert!(IngestTokens::open(&db).lookup(&token).await.is_none());
+    }
+}
@@ -1,36 +1,49 @@
 use std::net::SocketAddr;
-use axum::{routing::{get, post, put, delete}, Router};
+use axum::{extract::DefaultBodyLimit, middleware, routing::{get, post, put, delete}, Router};
 use tower_http::{cors::{Any, CorsLayer}, trace::TraceLayer};
-use tracing_subscriber::{fmt, EnvFilter};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
 
-mod news;
-use news::*;
-mod users;
-use users::*;
-mod logs;
-use logs::*;
-mod dashboard;
-use dashboard::*;
-mod settings;
-use settings::*;
-mod terminal;
-use terminal::*;
-mod files;
-use files::*;
+use untitled::{alerts::*, auth::*, dashboard::*, files::*, logs::*, news::*, settings::*, terminal::*, users::*};
+use untitled::{ip_filter, lockout, maintenance, metrics, permissions, rate_limit, request_id, storage, totp};
 
 #[tokio::main]
 async fn main() {
-    // Логи
-    let _ = fmt()
-        .with_env_filter(EnvFilter::from_default_env().add_directive("info".parse().unwrap()))
+    // Логи: в консоль по RUST_LOG, а в журнал панели — по уровню из настроек
+    let (capture, capture_handle) = CaptureLayer::new();
+    let _ = tracing_subscriber::registry()
+        .with(fmt::layer().with_filter(EnvFilter::from_default_env().add_directive("info".parse().unwrap())))
+        .with(capture)
         .try_init();
 
-    let news_store = NewsStore::new_with_mock();
-    let users_store = UsersStore::new_with_mock();
-    let logs_store = LogsStore::new_mock();
-    let settings_store = SettingsStore::new_default();
-    let term_store = TerminalStore::new_welcome();
-    let fs_store = FsStore::new_mock();
+    // Хранилище выбирается при старте (DC_STORAGE), мок-данные — только при первом запуске
+    let db = storage::open_from_env();
+    let news_store = NewsStore::open(&db);
+    let users_store = UsersStore::open(&db);
+    let logs_store = LogsStore::open(&db);
+    let settings_store = SettingsStore::open(&db);
+    let term_store = TerminalStore::open(&db);
+    let fs_store = FsStore::open(&db);
+    let sessions = SessionStore::open(&db);
+    let alerts_store = AlertsStore::open(&db, logs_store.searches().clone());
+    let metrics = metrics::Metrics::default();
+    let dashboard_store = DashboardStore::spawn_sampler(metrics.clone(), sessions.clone());
+    let auth_state = AuthState {
+        users: users_store.clone(),
+        sessions,
+        settings: settings_store.clone(),
+        logs: logs_store.clone(),
+        guard: lockout::LoginGuard::default(),
+        secure_cookies: secure_cookies_from_env(),
+    };
+    if !auth_state.secure_cookies { tracing::warn!("DC_INSECURE_COOKIES: session cookie is sent over plain HTTP"); }
+    let limiter = rate_limit::RateLimiter::new(settings_store.clone());
+    maintenance::spawn(settings_store.clone(), news_store.clone());
+    logs_store.spawn_retention(settings_store.clone());
+    logs_store.spawn_dedup(settings_store.clone());
+    capture_handle.attach(logs_store.clone(), settings_store.clone());
+    spawn_syslog(logs_store.clone(), settings_store.clone()).await;
+    spawn_tail(&db, logs_store.clone(), settings_store.clone());
+    alerts_store.spawn_evaluator(logs_store.clone(), settings_store.clone());
 
     let api = Router::new()
         // Новости
@@ -41,16 +54,36 @@ async fn main() {
         // Пользователи
         .route("/users", get(list_users).post(create_user))
         .route("/users/:id", delete(delete_user).put(update_user))
+        .route("/users/:id/2fa", delete(reset_two_factor))
         .with_state(users_store)
         // Логи
-        .route("/logs", get(list_logs).post(push_log))
+        .route("/logs", get(list_logs).post(push_log).layer(DefaultBodyLimit::max(128 * 1024)))
+        .route("/logs/batch", post(push_logs).layer(DefaultBodyLimit::max(4 * 1024 * 1024)))
         .route("/logs/stats", get(logs_stats))
+        .route("/logs/histogram", get(logs_histogram))
+        .route("/logs/stream", get(stream_logs))
+        .route("/logs/export", get(export_logs))
+        .route("/logs/searches", get(list_searches).post(create_search))
+        .route("/logs/searches/:id", get(get_search).put(update_search).delete(delete_search))
+        .route("/logs/pipelines", get(list_pipelines).post(create_pipeline))
+        .route("/logs/pipelines/test", post(test_pipeline))
+        .route("/logs/pipelines/:name", put(update_pipeline).delete(delete_pipeline))
+        .route("/logs/tokens", get(list_tokens).post(create_token))
+        .route("/logs/tokens/:id", delete(delete_token))
+        .route("/requests/:id", get(request_id::request_trace))
         .with_state(logs_store)
+        // Оповещения
+        .route("/alerts/rules", get(list_rules).post(create_rule))
+        .route("/alerts/rules/:id", put(update_rule).delete(delete_rule))
+        .route("/alerts/history", get(alert_history))
+        .with_state(alerts_store)
         // Дашборд
         .route("/dashboard", get(get_dashboard))
+        .route("/dashboard/routes", get(route_metrics))
+        .with_state(dashboard_store)
         // Настройки
         .route("/settings", get(get_settings).put(update_settings))
-        .with_state(settings_store)
+        .with_state(settings_store.clone())
         // Терминал
         .route("/terminal/history", get(get_history))
         .route("/terminal/exec", post(exec_command))
@@ -58,21 +91,46 @@ async fn main() {
         // Файлы
         .route("/files", get(list).post(create))
         .route("/files/:id", put(save).delete(remove))
-        .with_state(fs_store);
+        .with_state(fs_store)
+        // Сессия
+        .route("/auth/me", get(me))
+        .route("/auth/logout", post(logout))
+        .route("/auth/2fa/enroll", post(totp::enroll))
+        .route("/auth/2fa/confirm", post(totp::confirm))
+        // Разблокировка снимает и блокировки адресов в LoginGuard
+        .route("/users/:id/unlock", post(unlock_user))
+        .with_state(auth_state.clone())
+        // Всё, что выше, проходит через проверку сессии, режим обслуживания,
+        // лимит запросов и права на маршрут
+        .route_layer(middleware::from_fn(permissions::authorize))
+        .route_layer(middleware::from_fn_with_state(limiter.clone(), rate_limit::limit))
+        .route_layer(middleware::from_fn_with_state(settings_store.clone(), maintenance::guard))
+        .route_layer(middleware::from_fn_with_state(auth_state.clone(), require_auth))
+        .route("/auth/login", post(login).layer(middleware::from_fn_with_state(limiter, rate_limit::limit)))
+        .with_state(auth_state);
 
     let app = Router::new()
         .nest("/api", api)
+        .layer(middleware::from_fn_with_state(settings_store.clone(), ip_filter::filter))
+        .layer(metrics.layer())
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
                 .allow_methods(Any)
-                .allow_headers(Any),
+                .allow_headers(Any)
+                .expose_headers([request_id::REQUEST_ID]),
         )
-        .layer(TraceLayer::new_for_http());
+        // Идентификатор запроса назначается до всех слоёв, чтобы попасть и в трассировку
+        .layer(TraceLayer::new_for_http().make_span_with(|req: &axum::extract::Request| {
+            let request_id = req.headers().get(&request_id::REQUEST_ID).and_then(|v| v.to_str().ok()).unwrap_or_default();
+            tracing::debug_span!("request", method = %req.method(), uri = %req.uri(), version = ?req.version(), request_id)
+        }))
+        .layer(middleware::from_fn(request_id::assign));
 
     let addr: SocketAddr = "0.0.0.0:8080".parse().unwrap();
     tracing::info!(%addr, "Starting server");
-    axum::serve(tokio::net::TcpListener::bind(addr).await.unwrap(), app)
+    // ConnectInfo нужен для списка разрешённых IP и учёта попыток входа
+    axum::serve(tokio::net::TcpListener::bind(addr).await.unwrap(), app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 }
@@ -0,0 +1,151 @@
+use std::time::Duration;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use crate::{
+    auth::CurrentUser,
+    news::{ArticleStatus, NewsStore, UpsertArticle},
+    permissions::ALL,
+    settings::{General, SettingsStore},
+};
+
+// Retry-After, если время окончания работ не указано
+const DEFAULT_RETRY_SECS: i64 = 300;
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize)]
+pub struct MaintenanceNotice { pub maintenance: bool, pub message: String, pub until: Option<DateTime<Utc>> }
+
+fn notice_text(general: &General) -> String {
+    general.maintenance_message.clone().unwrap_or_else(|| "Сервер находится на техническом обслуживании".into())
+}
+
+// Ставится после `require_auth`. Администраторы работают как обычно; вход и сессия
+// доступны всем, чтобы администратор мог войти, а интерфейс — показать уведомление.
+pub async fn guard(State(settings): State<SettingsStore>, user: Option<CurrentUser>, req: Request, next: Next) -> Response {
+    let general = {
+        let cfg = settings.inner.read().await;
+        if !cfg.general.maintenance_mode { drop(cfg); return next.run(req).await; }
+        cfg.general.clone()
+    };
+    let is_admin = user.is_some_and(|CurrentUser(u)| u.has_permission(ALL));
+    let route = req.extensions().get::<MatchedPath>().map(|p| p.as_str()).unwrap_or_default();
+    if is_admin || route.starts_with("/api/auth/") { return next.run(req).await; }
+
+    let retry = general.maintenance_until
+        .map(|until| (until - Utc::now()).num_seconds())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(DEFAULT_RETRY_SECS);
+    let notice = MaintenanceNotice { maintenance: true, message: notice_text(&general), until: general.maintenance_until };
+    (StatusCode::SERVICE_UNAVAILABLE, [(header::RETRY_AFTER, retry.to_string())], Json(notice)).into_response()
+}
+
+fn announcement(general: &General, started: bool) -> UpsertArticle {
+    let (title, content) = if started {
+        let until = general.maintenance_until
+            .map(|u| format!(" Плановое окончание: {}.", u.format("%Y-%m-%d %H:%M UTC")))
+            .unwrap_or_default();
+        ("Начались технические работы".to_string(), format!("{}.{}", notice_text(general).trim_end_matches('.'), until))
+    } else {
+        ("Технические работы завершены".to_string(), "Сервер снова работает в обычном режиме.".to_string())
+    };
+    UpsertArticle {
+        title,
+        content: content.clone(),
+        markdown_content: content,
+        excerpt: String::new(),
+        author: general.server_name.clone(),
+        category: "Обслуживание".into(),
+        status: ArticleStatus::Published,
+        tags: vec!["обслуживание".into()],
+        featured: started,
+    }
+}
+
+// Следит за настройками: публикует новости о начале/окончании работ (если включено
+// General.maintenance_announce) и выключает режим, когда наступает maintenance_until
+pub fn spawn(settings: SettingsStore, news: NewsStore) {
+    let mut changes = settings.subscribe();
+    tokio::spawn(async move {
+        let mut active = changes.borrow_and_update().general.maintenance_mode;
+        let mut tick = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            tokio::select! {
+                changed = changes.changed() => {
+                    if changed.is_err() { break; }
+                    let general = changes.borrow_and_update().general.clone();
+                    if general.maintenance_mode == active { continue; }
+                    active = general.maintenance_mode;
+                    tracing::info!(active, "Maintenance mode changed");
+                    if general.maintenance_announce { news.create(announcement(&general, active)).await; }
+                }
+                _ = tick.tick() => {
+                    let expired = {
+                        let cfg = settings.inner.read().await;
+                        cfg.general.maintenance_mode && cfg.general.maintenance_until.is_some_and(|until| until <= Utc::now())
+                    };
+                    if expired {
+                        settings.update(|cfg| { cfg.general.maintenance_mode = false; cfg.general.maintenance_until = None; }).await;
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use axum::{body::{to_bytes, Body}, middleware, routing::get, Router};
+    use tower::ServiceExt;
+    use super::*;
+    use crate::{storage::{Db, MemoryStorage}, users::{User, UsersStore}};
+
+    async fn call(settings: &SettingsStore, user: Option<User>, uri: &str) -> Response {
+        let app = Router::new()
+            .route("/api/news", get(|| async {}))
+            .route("/api/auth/me", get(|| async {}))
+            .route_layer(middleware::from_fn_with_state(settings.clone(), guard));
+        let mut req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        if let Some(user) = user { req.extensions_mut().insert(CurrentUser(user)); }
+        app.oneshot(req).await.unwrap()
+    }
+
+    fn retry_after(res: &Response) -> i64 {
+        res.headers()[header::RETRY_AFTER].to_str().unwrap().parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn maintenance_answers_503_except_for_admins_and_sign_in() {
+        let db: Db = Arc::new(MemoryStorage::default());
+        let settings = SettingsStore::open(&db);
+        let users = UsersStore::open(&db);
+        let admin = users.find_by_username("admin").await;
+        let moderator = users.find_by_username("moderator1").await;
+        assert_eq!(call(&settings, moderator.clone(), "/api/news").await.status(), StatusCode::OK);
+
+        settings.update(|cfg| {
+            cfg.general.maintenance_mode = true;
+            cfg.general.maintenance_message = Some("Обновление базы".into());
+            cfg.general.maintenance_until = Some(Utc::now() + chrono::Duration::minutes(10));
+        }).await;
+        let res = call(&settings, moderator.clone(), "/api/news").await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!((590..=600).contains(&retry_after(&res)));
+        let notice: serde_json::Value = serde_json::from_slice(&to_bytes(res.into_body(), 4096).await.unwrap()).unwrap();
+        assert_eq!((notice["maintenance"].as_bool(), notice["message"].as_str()), (Some(true), Some("Обновление базы")));
+        assert_eq!(call(&settings, None, "/api/news").await.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(call(&settings, admin, "/api/news").await.status(), StatusCode::OK);
+        assert_eq!(call(&settings, moderator.clone(), "/api/auth/me").await.status(), StatusCode::OK);
+
+        // Без времени окончания — Retry-After по умолчанию
+        settings.update(|cfg| cfg.general.maintenance_until = None).await;
+        assert_eq!(retry_after(&call(&settings, moderator, "/api/news").await), DEFAULT_RETRY_SECS);
+    }
+}
@@ -0,0 +1,141 @@
+use std::{collections::HashMap, future::Future, pin::Pin, sync::{Arc, Mutex}, task::{Context, Poll}};
+use axum::{extract::{MatchedPath, Request}, http::Method, response::Response};
+use serde::Serialize;
+use tower::{Layer, Service};
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RouteStats { pub requests: u64, pub client_errors: u64, pub server_errors: u64 }
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteMetrics {
+    pub method: String,
+    pub route: String,
+    #[serde(flatten)]
+    pub stats: RouteStats,
+}
+
+// Маршрутов конечное число, но на всякий случай таблица ограничена; сверх лимита — в OVERFLOW
+const MAX_ROUTES: usize = 1024;
+const OTHER: &str = "OTHER";
+const OVERFLOW: (&str, &str) = (OTHER, "other");
+
+// Нестандартные методы клиента сводятся в один, чтобы не плодить записи
+fn method_name(method: &Method) -> &'static str {
+    [Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE, Method::HEAD, Method::OPTIONS, Method::TRACE, Method::CONNECT]
+        .iter()
+        .find(|m| *m == method)
+        .map_or(OTHER, |m| m.as_str())
+}
+
+// Счётчики запросов и ответов 4xx/5xx по маршрутам с момента старта
+#[derive(Debug, Clone, Default)]
+pub struct Metrics { routes: Arc<Mutex<HashMap<(&'static str, String), RouteStats>>> }
+
+impl Metrics {
+    fn record(&self, method: &'static str, route: String, status: u16) {
+        let mut routes = self.routes.lock().unwrap();
+        let key = if routes.len() >= MAX_ROUTES && !routes.contains_key(&(method, route.clone())) { (OVERFLOW.0, OVERFLOW.1.to_string()) } else { (method, route) };
+        let stats = routes.entry(key).or_default();
+        stats.requests += 1;
+        match status {
+            400..=499 => stats.client_errors += 1,
+            500..=599 => stats.server_errors += 1,
+            _ => {}
+        }
+    }
+
+    pub fn totals(&self) -> RouteStats {
+        self.routes.lock().unwrap().values().fold(RouteStats::default(), |mut acc, s| {
+            acc.requests += s.requests;
+            acc.client_errors += s.client_errors;
+            acc.server_errors += s.server_errors;
+            acc
+        })
+    }
+
+    pub fn by_route(&self) -> Vec<RouteMetrics> {
+        let mut routes: Vec<RouteMetrics> = self.routes.lock().unwrap().iter()
+            .map(|((method, route), stats)| RouteMetrics { method: method.to_string(), route: route.clone(), stats: stats.clone() })
+            .collect();
+        routes.sort_by_key(|r| std::cmp::Reverse(r.stats.requests));
+        routes
+    }
+
+    pub fn layer(&self) -> MetricsLayer { MetricsLayer { metrics: self.clone() } }
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricsLayer { metrics: Metrics }
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service { MetricsService { inner, metrics: self.metrics.clone() } }
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricsService<S> { inner: S, metrics: Metrics }
+
+impl<S> Service<Request> for MetricsService<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> { self.inner.poll_ready(cx) }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let method = method_name(req.method());
+        // Шаблон маршрута, а не конкретный путь — иначе /news/<uuid> размножит записи
+        let route = req.extensions().get::<MatchedPath>().map(|p| p.as_str().to_string()).unwrap_or_else(|| "unmatched".into());
+        let metrics = self.metrics.clone();
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            metrics.record(method, route, res.status().as_u16());
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::StatusCode, routing::get, Router};
+    use tower::ServiceExt;
+    use super::*;
+
+    #[tokio::test]
+    async fn aggregates_by_route_template_and_known_method() {
+        let metrics = Metrics::default();
+        let app = Router::new()
+            .route("/news/:id", get(|| async { StatusCode::OK }).post(|| async { StatusCode::INTERNAL_SERVER_ERROR }))
+            .layer(metrics.layer());
+        for (method, uri) in [("GET", "/news/1"), ("GET", "/news/2"), ("POST", "/news/3"), ("GET", "/missing"), ("FOO", "/news/4"), ("BAR", "/news/5")] {
+            let req = Request::builder().method(method).uri(uri).body(Body::empty()).unwrap();
+            app.clone().oneshot(req).await.unwrap();
+        }
+        let rows: Vec<(String, String, u64, u64, u64)> = metrics.by_route().into_iter()
+            .map(|r| (r.method, r.route, r.stats.requests, r.stats.client_errors, r.stats.server_errors))
+            .collect();
+        assert_eq!(rows[0], ("GET".into(), "/news/:id".into(), 2, 0, 0));
+        assert_eq!(rows[1], ("OTHER".into(), "/news/:id".into(), 2, 2, 0));
+        assert!(rows.contains(&("POST".into(), "/news/:id".into(), 1, 0, 1)));
+        assert!(rows.contains(&("GET".into(), "unmatched".into(), 1, 1, 0)));
+        let totals = metrics.totals();
+        assert_eq!((totals.requests, totals.client_errors, totals.server_errors), (6, 3, 1));
+    }
+
+    #[test]
+    fn table_is_capped() {
+        let metrics = Metrics::default();
+        for i in 0..MAX_ROUTES + 10 { metrics.record("GET", format!("/r{}", i), 200); }
+        metrics.record("GET", "/r0".into(), 200);
+        let rows = metrics.by_route();
+        assert_eq!(rows.len(), MAX_ROUTES + 1);
+        assert_eq!(rows.iter().find(|r| r.route == "other").map(|r| r.stats.requests), Some(10));
+        assert_eq!(rows.iter().find(|r| r.route == "/r0").map(|r| r.stats.requests), Some(2));
+    }
+}
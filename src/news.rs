@@ -4,6 +4,7 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 use uuid::Uuid;
+use crate::storage::{Collection, Db};
 
 // Модель статьи
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,50 +52,81 @@ pub struct UpsertArticle {
     pub featured: bool,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct NewsStore {
     inner: Arc<RwLock<Vec<NewsArticle>>>,
+    db: Collection,
 }
 
 impl NewsStore {
-    pub fn new_with_mock() -> Self {
-        let mut initial: Vec<NewsArticle> = Vec::new();
-        initial.push(NewsArticle {
-            id: Uuid::new_v4(),
-            title: "Обновление системы безопасности".into(),
-            content: "Подробное описание обновления системы безопасности...".into(),
-            markdown_content: "# Обновление системы безопасности".into(),
-            excerpt: "Важные улучшения в системе безопасности нашего проекта".into(),
-            author: "Администратор".into(),
-            category: "Безопасность".into(),
-            status: ArticleStatus::Published,
-            publish_date: Utc::now(),
-            views: 1250,
-            likes: 89,
-            comments: 23,
-            tags: vec!["безопасность".into(), "обновление".into()],
-            featured: true,
-        });
-        initial.push(NewsArticle {
+    pub fn open(db: &Db) -> Self {
+        let db = Collection::new(db, "news").newest_first();
+        let initial = db.load_or_seed(|a: &NewsArticle| a.id.to_string(), Self::mock);
+        Self { inner: Arc::new(RwLock::new(initial)), db }
+    }
+
+    pub async fn create(&self, payload: UpsertArticle) -> NewsArticle {
+        let mut data = self.inner.write().await;
+        let article = NewsArticle {
             id: Uuid::new_v4(),
-            title: "Новые функции в API v2.0".into(),
-            content: "Описание новых функций API...".into(),
-            markdown_content: "# API v2.0 - Новые возможности".into(),
-            excerpt: "Представляем новые возможности нашего API".into(),
-            author: "Разработчик".into(),
-            category: "Разработка".into(),
-            status: ArticleStatus::Published,
+            title: payload.title,
+            content: payload.content,
+            markdown_content: payload.markdown_content.clone(),
+            excerpt: if payload.excerpt.is_empty() {
+                payload.markdown_content.chars().take(120).collect::<String>()
+            } else { payload.excerpt }
+                ,
+            author: payload.author,
+            category: payload.category,
+            status: payload.status,
             publish_date: Utc::now(),
-            views: 890,
-            likes: 67,
-            comments: 15,
-            tags: vec!["api".into(), "разработка".into()],
-            featured: false,
-        });
-        Self { inner: Arc::new(RwLock::new(initial)) }
+            views: 0,
+            likes: 0,
+            comments: 0,
+            tags: payload.tags,
+            featured: payload.featured,
+        };
+        data.insert(0, article.clone());
+        self.db.put(&article.id.to_string(), &article);
+        article
     }
 
-    pub fn layer(self) -> axum::extract::State<Self> { State(self) }
+    fn mock() -> Vec<NewsArticle> {
+        vec![
+            NewsArticle {
+                id: Uuid::new_v4(),
+                title: "Обновление системы безопасности".into(),
+                content: "Подробное описание обновления системы безопасности...".into(),
+                markdown_content: "# Обновление системы безопасности".into(),
+                excerpt: "Важные улучшения в системе безопасности нашего проекта".into(),
+                author: "Администратор".into(),
+                category: "Безопасность".into(),
+                status: ArticleStatus::Published,
+                publish_date: Utc::now(),
+                views: 1250,
+                likes: 89,
+                comments: 23,
+                tags: vec!["безопасность".into(), "обновление".into()],
+                featured: true,
+            },
+            NewsArticle {
+                id: Uuid::new_v4(),
+                title: "Новые функции в API v2.0".into(),
+                content: "Описание новых функций API...".into(),
+                markdown_content: "# API v2.0 - Новые возможности".into(),
+                excerpt: "Представляем новые возможности нашего API".into(),
+                author: "Разработчик".into(),
+                category: "Разработка".into(),
+                status: ArticleStatus::Published,
+                publish_date: Utc::now(),
+                views: 890,
+                likes: 67,
+                comments: 15,
+                tags: vec!["api".into(), "разработка".into()],
+                featured: false,
+            },
+        ]
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -142,28 +174,7 @@ pub async fn get_news(State(store): State<NewsStore>, Path(id): Path<Uuid>) -> i
 }
 
 pub async fn create_news(State(store): State<NewsStore>, Json(payload): Json<UpsertArticle>) -> impl IntoResponse {
-    let mut data = store.inner.write().await;
-    let article = NewsArticle {
-        id: Uuid::new_v4(),
-        title: payload.title,
-        content: payload.content,
-        markdown_content: payload.markdown_content.clone(),
-        excerpt: if payload.excerpt.is_empty() {
-            payload.markdown_content.chars().take(120).collect::<String>()
-        } else { payload.excerpt }
-            ,
-        author: payload.author,
-        category: payload.category,
-        status: payload.status,
-        publish_date: Utc::now(),
-        views: 0,
-        likes: 0,
-        comments: 0,
-        tags: payload.tags,
-        featured: payload.featured,
-    };
-    data.insert(0, article.clone());
-    (StatusCode::CREATED, Json(article))
+    (StatusCode::CREATED, Json(store.create(payload).await))
 }
 
 pub async fn update_news(State(store): State<NewsStore>, Path(id): Path<Uuid>, Json(payload): Json<UpsertArticle>) -> impl IntoResponse {
@@ -178,6 +189,7 @@ pub async fn update_news(State(store): State<NewsStore>, Path(id): Path<Uuid>, J
         item.status = payload.status;
         item.tags = payload.tags;
         item.featured = payload.featured;
+        store.db.put(&id.to_string(), item);
         return Json(item.clone()).into_response();
     }
     (StatusCode::NOT_FOUND, "Not found").into_response()
@@ -187,7 +199,7 @@ pub async fn delete_news(State(store): State<NewsStore>, Path(id): Path<Uuid>) -
     let mut data = store.inner.write().await;
     let before = data.len();
     data.retain(|a| a.id != id);
-    if data.len() != before { return StatusCode::NO_CONTENT.into_response(); }
+    if data.len() != before { store.db.remove(&id.to_string()); return StatusCode::NO_CONTENT.into_response(); }
     (StatusCode::NOT_FOUND, "Not found").into_response()
 }
 
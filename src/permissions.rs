@@ -0,0 +1,168 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use crate::{auth::CurrentUser, logs::{accepts_ingest_token, IngestClient}, users::{User, UserRole}};
+
+pub const ALL: &str = "all";
+pub const MODERATE: &str = "moderate";
+pub const EDIT: &str = "edit";
+pub const DELETE: &str = "delete";
+
+// Права, которые роль даёт сама по себе, без явного списка у пользователя
+fn role_grants(role: &UserRole) -> &'static [&'static str] {
+    match role {
+        UserRole::Admin => &[ALL],
+        UserRole::Moderator => &[MODERATE, EDIT, DELETE],
+        UserRole::User | UserRole::Banned => &[],
+    }
+}
+
+impl User {
+    pub fn has_permission(&self, permission: &str) -> bool {
+        if self.role == UserRole::Banned { return false; }
+        let granted = |p: &str| p == ALL || p == permission;
+        role_grants(&self.role).iter().any(|p| granted(p)) || self.permissions.iter().any(|p| granted(p))
+    }
+}
+
+// Требования к маршрутам. None — достаточно быть аутентифицированным
+// (или анонимным, если Api.require_auth выключен).
+// Путь — шаблон маршрута внутри /api, как он записан в main.rs.
+fn required_permission(method: &Method, route: &str) -> Option<&'static str> {
+    match (method.as_str(), route) {
+        (_, "/auth/me" | "/auth/logout" | "/auth/2fa/enroll" | "/auth/2fa/confirm") => None,
+        ("GET", "/news" | "/news/stats" | "/news/:id" | "/files" | "/dashboard") => None,
+        // Свои и общие поиски; записи по ним отдают маршруты журнала со своими правами
+        ("GET", "/logs/searches" | "/logs/searches/:id") => None,
+        ("GET", "/users") => Some(MODERATE),
+        ("GET", "/settings") => Some(MODERATE),
+        ("GET", "/alerts/rules" | "/alerts/history") => Some(MODERATE),
+        // В журнале — записи безопасности с адресами и именами пользователей
+        ("GET", "/logs" | "/logs/stats" | "/logs/histogram" | "/logs/stream" | "/logs/export" | "/requests/:id") => Some(MODERATE),
+        ("GET", "/logs/pipelines" | "/dashboard/routes") => Some(MODERATE),
+        ("GET", "/logs/tokens") => Some(ALL),
+        ("GET", "/terminal/history") => Some(ALL),
+
+        ("POST", "/news") | ("PUT", "/news/:id") => Some(EDIT),
+        ("DELETE", "/news/:id") => Some(DELETE),
+        ("POST", "/logs" | "/logs/batch") => Some(EDIT),
+        // Свои поиски ведёт любой пользователь; права владельца проверяет обработчик
+        ("POST", "/logs/searches") | ("PUT" | "DELETE", "/logs/searches/:id") => None,
+        ("POST", "/files") | ("PUT", "/files/:id") => Some(EDIT),
+        ("DELETE", "/files/:id") => Some(DELETE),
+        ("POST", "/users") | ("PUT", "/users/:id") | ("DELETE", "/users/:id") => Some(ALL),
+        ("DELETE", "/users/:id/2fa") | ("POST", "/users/:id/unlock") => Some(ALL),
+        ("PUT", "/settings") => Some(ALL),
+        ("POST", "/alerts/rules") | ("PUT" | "DELETE", "/alerts/rules/:id") => Some(ALL),
+        ("POST", "/logs/pipelines/test") => Some(MODERATE),
+        ("POST", "/logs/pipelines") | ("PUT" | "DELETE", "/logs/pipelines/:name") => Some(ALL),
+        ("POST", "/logs/tokens") | ("DELETE", "/logs/tokens/:id") => Some(ALL),
+        ("POST", "/terminal/exec") => Some(ALL),
+        // Всё, что не описано явно, — только для администратора
+        _ => Some(ALL),
+    }
+}
+
+// Запускается после `require_auth`, поэтому CurrentUser (или IngestClient) уже в запросе, если сессия есть
+pub async fn authorize(user: Option<CurrentUser>, req: Request, next: Next) -> Response {
+    if let Some(CurrentUser(u)) = &user && u.role == UserRole::Banned {
+        return (StatusCode::FORBIDDEN, "Account is banned").into_response();
+    }
+    let route = req.extensions().get::<MatchedPath>().map(|p| p.as_str()).unwrap_or_else(|| req.uri().path());
+    let route = route.strip_prefix("/api").unwrap_or(route);
+    if let Some(permission) = required_permission(req.method(), route) {
+        let ingest = req.extensions().get::<IngestClient>().is_some() && accepts_ingest_token(req.method(), route);
+        match &user {
+            // Сервис с токеном приёма вместо права EDIT
+            None if ingest => {}
+            None => return (StatusCode::UNAUTHORIZED, "Authentication required").into_response(),
+            Some(CurrentUser(u)) if !u.has_permission(permission) => {
+                return (StatusCode::FORBIDDEN, format!("Missing permission: {}", permission)).into_response();
+            }
+            Some(_) => {}
+        }
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, middleware, routing::{delete, get}, Router};
+    use chrono::Utc;
+    use tower::Service;
+    use uuid::Uuid;
+    use super::*;
+    use crate::users::{Credentials, UserStatus};
+
+    fn user(role: UserRole, permissions: &[&str]) -> User {
+        User {
+            id: Uuid::new_v4(), username: "u".into(), email: "u@example.com".into(), full_name: "U".into(), role, status: UserStatus::Active,
+            join_date: Utc::now(), last_activity: String::new(), posts: 0, reputation: 0,
+            permissions: permissions.iter().map(|p| p.to_string()).collect(), credentials: Credentials::default(),
+        }
+    }
+
+    async fn status(user: Option<User>, method: Method, uri: &str) -> StatusCode {
+        let mut app = Router::new()
+            .route("/api/news", get(|| async {}).post(|| async {}))
+            .route("/api/news/:id", delete(|| async {}))
+            .route("/api/users/:id", delete(|| async {}))
+            .route("/api/settings", get(|| async {}).put(|| async {}))
+            .route("/api/logs", get(|| async {}))
+            .route("/api/unlisted", get(|| async {}).put(|| async {}))
+            .route_layer(middleware::from_fn(authorize));
+        let mut req = Request::builder().method(method).uri(uri).body(Body::empty()).unwrap();
+        if let Some(user) = user { req.extensions_mut().insert(CurrentUser(user)); }
+        app.call(req).await.unwrap().status()
+    }
+
+    #[test]
+    fn roles_and_explicit_permissions_grant_access() {
+        assert!(user(UserRole::Admin, &[]).has_permission(DELETE));
+        assert!(user(UserRole::Moderator, &[]).has_permission(EDIT));
+        assert!(!user(UserRole::Moderator, &[]).has_permission(ALL));
+        assert!(user(UserRole::User, &[EDIT]).has_permission(EDIT));
+        assert!(!user(UserRole::User, &[EDIT]).has_permission(DELETE));
+        assert!(user(UserRole::User, &[ALL]).has_permission(DELETE));
+        // Блокировка отменяет и роль, и явные права
+        assert!(!user(UserRole::Banned, &[ALL]).has_permission(EDIT));
+    }
+
+    #[tokio::test]
+    async fn routes_require_their_permission() {
+        let admin = || Some(user(UserRole::Admin, &[]));
+        let moderator = || Some(user(UserRole::Moderator, &[]));
+        let plain = || Some(user(UserRole::User, &[]));
+        assert_eq!(status(moderator(), Method::POST, "/api/news").await, StatusCode::OK);
+        assert_eq!(status(plain(), Method::POST, "/api/news").await, StatusCode::FORBIDDEN);
+        assert_eq!(status(None, Method::POST, "/api/news").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(moderator(), Method::DELETE, "/api/news/1").await, StatusCode::OK);
+        assert_eq!(status(moderator(), Method::DELETE, "/api/users/1").await, StatusCode::FORBIDDEN);
+        assert_eq!(status(admin(), Method::DELETE, "/api/users/1").await, StatusCode::OK);
+        assert_eq!(status(moderator(), Method::GET, "/api/settings").await, StatusCode::OK);
+        assert_eq!(status(plain(), Method::GET, "/api/settings").await, StatusCode::FORBIDDEN);
+        assert_eq!(status(moderator(), Method::PUT, "/api/settings").await, StatusCode::FORBIDDEN);
+        assert_eq!(status(moderator(), Method::PUT, "/api/unlisted").await, StatusCode::FORBIDDEN);
+        assert_eq!(status(plain(), Method::GET, "/api/news").await, StatusCode::OK);
+        assert_eq!(status(None, Method::GET, "/api/news").await, StatusCode::OK);
+        assert_eq!(status(Some(user(UserRole::Banned, &[])), Method::GET, "/api/news").await, StatusCode::FORBIDDEN);
+        // Чтение журнала и неописанные GET закрыты так же, как остальные методы
+        assert_eq!(status(moderator(), Method::GET, "/api/logs").await, StatusCode::OK);
+        assert_eq!(status(plain(), Method::GET, "/api/logs").await, StatusCode::FORBIDDEN);
+        assert_eq!(status(None, Method::GET, "/api/logs").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(moderator(), Method::GET, "/api/unlisted").await, StatusCode::FORBIDDEN);
+        assert_eq!(status(admin(), Method::GET, "/api/unlisted").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn denial_names_missing_permission() {
+        let mut app = Router::new().route("/api/users/:id", delete(|| async {})).route_layer(middleware::from_fn(authorize));
+        let mut req = Request::builder().method(Method::DELETE).uri("/api/users/1").body(Body::empty()).unwrap();
+        req.extensions_mut().insert(CurrentUser(user(UserRole::Moderator, &[])));
+        let body = axum::body::to_bytes(app.call(req).await.unwrap().into_body(), 1024).await.unwrap();
+        assert_eq!(&body[..], b"Missing permission: all");
+    }
+}
@@ -0,0 +1,152 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::Instant};
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use crate::{auth::CurrentUser, ip_filter::ClientIp, logs::IngestClient, settings::SettingsStore};
+
+// Отдельные, более строгие лимиты (запросов в минуту) для чувствительных маршрутов
+const STRICT_ROUTES: &[(&str, u32)] = &[("/api/auth/login", 10), ("/api/terminal/exec", 20)];
+// Выше этого числа корзин полностью восстановившиеся удаляются
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Debug)]
+struct Bucket { capacity: f64, tokens: f64, last: Instant }
+
+impl Bucket {
+    fn new(capacity: f64) -> Self { Self { capacity, tokens: capacity, last: Instant::now() } }
+
+    // Пополнение: capacity токенов в минуту. Новый лимит из настроек применяется сразу
+    fn refill(&mut self, capacity: f64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.capacity = capacity;
+        self.tokens = (self.tokens + elapsed * capacity / 60.0).min(capacity);
+    }
+
+    fn rate(&self) -> f64 { self.capacity / 60.0 }
+    fn secs_until_token(&self) -> u64 { ((1.0 - self.tokens) / self.rate()).ceil().max(1.0) as u64 }
+    fn secs_until_full(&self) -> u64 { ((self.capacity - self.tokens) / self.rate()).ceil() as u64 }
+}
+
+// Результат проверки одной корзины — для заголовков X-RateLimit-*
+struct Verdict { limit: u32, remaining: u32, reset: u64, retry_after: Option<u64> }
+
+impl Verdict {
+    fn of(bucket: &Bucket, limit: u32, retry_after: Option<u64>) -> Self {
+        Self { limit, remaining: bucket.tokens.floor() as u32, reset: bucket.secs_until_full(), retry_after }
+    }
+}
+
+// Token bucket: общий лимит сервера (Performance.max_requests_per_minute),
+// лимит на клиента (Api.rate_limit) — по пользователю, а без сессии по IP,
+// и строгие лимиты на отдельные маршруты. Всё включается Performance.rate_limit_enabled.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    settings: SettingsStore,
+    global: Arc<Mutex<Option<Bucket>>>,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: SettingsStore) -> Self {
+        Self { settings, global: Arc::default(), buckets: Arc::default() }
+    }
+
+    // Корзина клиента или маршрута по ключу; None — общая корзина сервера
+    fn bucket<'a>(buckets: &'a mut HashMap<String, Bucket>, global: &'a mut Option<Bucket>, key: Option<&str>, limit: u32) -> &'a mut Bucket {
+        match key {
+            Some(key) => buckets.entry(key.to_string()).or_insert_with(|| Bucket::new(limit as f64)),
+            None => global.get_or_insert_with(|| Bucket::new(limit as f64)),
+        }
+    }
+
+    // Запрос проходит, только если токен есть во всех его корзинах, и тогда берётся из
+    // каждой: отказ одной корзины не расходует остальные. Ok — итог последней корзины,
+    // Err — первой пустой.
+    fn admit(&self, quotas: &[(Option<String>, u32)]) -> Result<Option<Verdict>, Verdict> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let mut global = self.global.lock().unwrap();
+        if buckets.len() > MAX_BUCKETS {
+            buckets.retain(|_, b| { b.refill(b.capacity); b.tokens < b.capacity });
+        }
+        for (key, limit) in quotas {
+            let bucket = Self::bucket(&mut buckets, &mut global, key.as_deref(), *limit);
+            bucket.refill(*limit as f64);
+            if bucket.tokens < 1.0 { return Err(Verdict::of(bucket, *limit, Some(bucket.secs_until_token()))); }
+        }
+        Ok(quotas.iter().map(|(key, limit)| {
+            let bucket = Self::bucket(&mut buckets, &mut global, key.as_deref(), *limit);
+            bucket.tokens -= 1.0;
+            Verdict::of(bucket, *limit, None)
+        }).last())
+    }
+}
+
+fn set_headers(headers: &mut HeaderMap, v: &Verdict) {
+    headers.insert("x-ratelimit-limit", HeaderValue::from(v.limit));
+    headers.insert("x-ratelimit-remaining", HeaderValue::from(v.remaining));
+    headers.insert("x-ratelimit-reset", HeaderValue::from(v.reset));
+}
+
+fn too_many(v: &Verdict) -> Response {
+    let mut res = (StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded").into_response();
+    set_headers(res.headers_mut(), v);
+    if let Some(retry_after) = v.retry_after { res.headers_mut().insert("retry-after", HeaderValue::from(retry_after)); }
+    res
+}
+
+// Ставится после `require_auth`, чтобы знать пользователя
+pub async fn limit(State(limiter): State<RateLimiter>, user: Option<CurrentUser>, ClientIp(ip): ClientIp, req: Request, next: Next) -> Response {
+    let (enabled, global_limit, client_limit) = {
+        let cfg = limiter.settings.inner.read().await;
+        (cfg.performance.rate_limit_enabled, cfg.performance.max_requests_per_minute, cfg.api.rate_limit)
+    };
+    if !enabled { return next.run(req).await; }
+
+    let client = match (&user, req.extensions().get::<IngestClient>()) {
+        (Some(CurrentUser(u)), _) => format!("user:{}", u.id),
+        (None, Some(IngestClient(token))) => format!("token:{}", token.id),
+        (None, None) => format!("ip:{}", ip),
+    };
+    let route = req.extensions().get::<MatchedPath>().map(|p| p.as_str().to_string()).unwrap_or_default();
+    // Заголовки X-RateLimit-* описывают лимит клиента, поэтому его корзина — последняя
+    let mut quotas = Vec::new();
+    if let Some((_, strict)) = STRICT_ROUTES.iter().find(|(r, _)| *r == route) { quotas.push((Some(format!("{}:{}", route, client)), *strict)); }
+    if global_limit > 0 { quotas.push((None, global_limit)); }
+    if client_limit > 0 { quotas.push((Some(client), client_limit)); }
+    let v = match limiter.admit(&quotas) {
+        Ok(v) => v.filter(|_| client_limit > 0),
+        Err(v) => return too_many(&v),
+    };
+
+    let mut res = next.run(req).await;
+    if let Some(v) = v { set_headers(res.headers_mut(), &v); }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Db, MemoryStorage};
+
+    #[test]
+    fn rejected_request_spends_no_tokens() {
+        let db: Db = Arc::new(MemoryStorage::default());
+        let limiter = RateLimiter::new(SettingsStore::open(&db));
+        let strict = (Some("/api/auth/login:ip:127.0.0.1".to_string()), 2);
+        let both = [strict.clone(), (None, 1)];
+        assert!(limiter.admit(&both).is_ok());
+        // Общая корзина пуста — строгая не должна потерять свой второй токен
+        let rejected = limiter.admit(&both).err().unwrap();
+        assert_eq!((rejected.limit, rejected.remaining), (1, 0));
+        assert!(rejected.retry_after.is_some());
+        let last = limiter.admit(std::slice::from_ref(&strict)).ok().flatten().unwrap();
+        assert_eq!((last.limit, last.remaining), (2, 0));
+        assert!(limiter.admit(&[strict]).is_err());
+        assert!(limiter.admit(&[]).is_ok_and(|v| v.is_none()));
+    }
+}
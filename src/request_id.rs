@@ -0,0 +1,141 @@
+use std::ops::ControlFlow;
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::logs::{LogCategory, LogEntry, LogFilter, LogsStore};
+
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+const MAX_LEN: usize = 128;
+// Запрос обрабатывается заведомо быстрее, поэтому его записи ищутся только в таком интервале вокруг известной
+const TRACE_SPAN: Duration = Duration::hours(1);
+
+tokio::task_local! {
+    static CURRENT: String;
+}
+
+// Идентификатор запроса, который сейчас обрабатывается в этой задаче
+pub fn current() -> Option<String> { CURRENT.try_with(|id| id.clone()).ok() }
+
+// Чужой идентификатор принимается, только если он короткий и без спецсимволов
+fn accept(value: &HeaderValue) -> Option<String> {
+    let id = value.to_str().ok()?.trim();
+    let valid = !id.is_empty() && id.len() <= MAX_LEN && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+    valid.then(|| id.to_string())
+}
+
+// Самый внешний слой: берёт X-Request-Id клиента или выдаёт новый, возвращает его
+// в ответе и делает доступным всем записям журнала, созданным по ходу запроса
+pub async fn assign(mut req: Request, next: Next) -> Response {
+    let id = req.headers().get(&REQUEST_ID).and_then(accept).unwrap_or_else(|| Uuid::new_v4().to_string());
+    let value = HeaderValue::from_str(&id).expect("request id is a valid header value");
+    req.headers_mut().insert(REQUEST_ID, value.clone());
+    let mut res = CURRENT.scope(id, next.run(req)).await;
+    res.headers_mut().insert(REQUEST_ID, value);
+    res
+}
+
+#[derive(Debug, Serialize)]
+pub struct RequestTrace {
+    pub request_id: String,
+    pub logs: Vec<LogEntry>,
+    // Записи журнала безопасности: входы, блокировки, отказы по IP
+    pub audit: Vec<LogEntry>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TraceParams {
+    // Время любой записи запроса — для запросов, уже вытесненных из окна в памяти
+    #[serde(default)]
+    pub at: Option<DateTime<Utc>>,
+}
+
+// Всё, что записано в журнал за время запроса, в хронологическом порядке. Поиск
+// ограничен TRACE_SPAN вокруг записи из окна в памяти (или ?at=), так что диск
+// читается только за этот интервал, а не целиком. Если в окне записей запроса нет,
+// а ?at= не задан, — 404: записи могут быть на диске, но искать их по всему журналу не будем.
+pub async fn request_trace(State(store): State<LogsStore>, Path(id): Path<String>, Query(params): Query<TraceParams>) -> Result<Json<RequestTrace>, (StatusCode, String)> {
+    let filter = LogFilter { request_id: Some(id.clone()), ..Default::default() };
+    let (mut logs, mut audit) = (Vec::new(), Vec::new());
+    let anchor = match params.at {
+        Some(at) => Some(at),
+        None => store.newest_in_window(&filter.compile().expect("request id filter is always valid")).await.map(|l| l.timestamp),
+    };
+    let Some(anchor) = anchor else {
+        return Err((StatusCode::NOT_FOUND, "No recent log entries for this request; pass ?at=<RFC 3339 time> to search older entries".into()));
+    };
+    let filter = LogFilter { from: Some(anchor - TRACE_SPAN), to: Some(anchor + TRACE_SPAN), ..filter };
+    let matcher = filter.compile().expect("request id filter is always valid");
+    store.scan(&matcher, None, |log| {
+        if log.category == LogCategory::Security { audit.push(log) } else { logs.push(log) }
+        ControlFlow::Continue(())
+    }).await;
+    logs.reverse();
+    audit.reverse();
+    Ok(Json(RequestTrace { request_id: id, logs, audit }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use crate::{logs::LogLevel, settings::LogsConfig, storage::{Db, MemoryStorage}};
+
+    fn entry(request_id: &str, category: LogCategory, age: Duration) -> LogEntry {
+        let mut log = LogEntry::new(LogLevel::Info, category, format!("{} {}", request_id, age.num_minutes()), "test");
+        log.request_id = Some(request_id.into());
+        log.timestamp = Utc::now() - age;
+        log
+    }
+
+    async fn trace(store: &LogsStore, id: &str, at: Option<DateTime<Utc>>) -> Result<RequestTrace, StatusCode> {
+        request_trace(State(store.clone()), Path(id.into()), Query(TraceParams { at })).await.map(|t| t.0).map_err(|e| e.0)
+    }
+
+    #[tokio::test]
+    async fn trace_is_bounded_around_known_entry() {
+        let db: Db = Arc::new(MemoryStorage::default());
+        let store = LogsStore::open_in(&db, None);
+        store.append_many(vec![
+            entry("req-1", LogCategory::Api, Duration::hours(3)),
+            entry("req-1", LogCategory::Api, Duration::seconds(2)),
+            entry("req-2", LogCategory::Api, Duration::seconds(1)),
+            entry("req-1", LogCategory::Security, Duration::seconds(1)),
+            entry("req-1", LogCategory::Api, Duration::zero()),
+        ]).await;
+
+        let found = trace(&store, "req-1", None).await.unwrap();
+        assert_eq!(found.logs.iter().map(|l| l.message.as_str()).collect::<Vec<_>>(), ["req-1 0", "req-1 0"]);
+        assert!(found.logs[0].timestamp <= found.logs[1].timestamp);
+        assert_eq!(found.audit.len(), 1);
+        // Давние записи того же id находятся только по ?at=
+        let old = trace(&store, "req-1", Some(Utc::now() - Duration::hours(3))).await.unwrap();
+        assert_eq!(old.logs.iter().map(|l| l.message.as_str()).collect::<Vec<_>>(), ["req-1 180"]);
+        assert_eq!(trace(&store, "req-3", None).await.err(), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn evicted_request_needs_a_time_hint() {
+        let dir = std::env::temp_dir().join(format!("dc_trace_{}", Uuid::new_v4()));
+        let db: Db = Arc::new(MemoryStorage::default());
+        let store = LogsStore::open_in(&db, Some(dir.clone()));
+        let policy = LogsConfig { memory_entries: 10, max_age_days: 0, ..LogsConfig::default() };
+        store.apply_retention(&policy).await;
+        let at = store.append(entry("req-1", LogCategory::Api, Duration::minutes(5))).await.unwrap().timestamp;
+        store.append_many((0..50).map(|i| entry(&format!("other-{}", i), LogCategory::Api, Duration::zero())).collect()).await;
+        // Дожидается потока записи, после чего запись запроса вытесняется из окна
+        store.apply_retention(&policy).await;
+
+        assert_eq!(trace(&store, "req-1", None).await.err(), Some(StatusCode::NOT_FOUND));
+        let found = trace(&store, "req-1", Some(at)).await.unwrap();
+        assert_eq!(found.logs.iter().map(|l| l.message.as_str()).collect::<Vec<_>>(), ["req-1 5"]);
+        drop(store);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
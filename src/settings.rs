@@ -1,7 +1,9 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::{collections::HashMap, sync::Arc};
+use chrono::{DateTime, Utc};
+use tokio::sync::{watch, RwLock};
+use crate::{ip_filter::{self, ClientIp}, logs::{self, CaptureLevel, LogCategory, TailSource}, storage::{Collection, Db}};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
@@ -12,38 +14,133 @@ pub struct ServerConfig {
     pub performance: Performance,
     pub notifications: Notifications,
     pub api: Api,
+    #[serde(default)]
+    pub logs: LogsConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct General { pub server_name: String, pub description: String, pub admin_email: String, pub timezone: String, pub language: String, pub maintenance_mode: bool }
+pub struct General {
+    pub server_name: String,
+    pub description: String,
+    pub admin_email: String,
+    pub timezone: String,
+    pub language: String,
+    pub maintenance_mode: bool,
+    // Текст для пользователей и плановое окончание работ
+    #[serde(default)]
+    pub maintenance_message: Option<String>,
+    #[serde(default)]
+    pub maintenance_until: Option<DateTime<Utc>>,
+    // Публиковать новость о начале и окончании работ
+    #[serde(default)]
+    pub maintenance_announce: bool,
+}
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Connection { pub host: String, pub port: u16, pub protocol: String, pub ssl_enabled: bool, pub api_endpoint: String, pub connection_timeout: u16, pub retry_attempts: u8, pub keep_alive: bool }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Database { pub host: String, pub port: u16, pub name: String, pub max_connections: u32, pub timeout: u16, pub auto_backup: bool, pub backup_interval: u16 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Security { pub enable_ssl: bool, pub require_two_factor: bool, pub session_timeout: u16, pub max_login_attempts: u8, pub ip_whitelist: bool, pub allowed_ips: Vec<String> }
+pub struct Security {
+    pub enable_ssl: bool,
+    pub require_two_factor: bool,
+    pub session_timeout: u16,
+    pub max_login_attempts: u8,
+    pub ip_whitelist: bool,
+    pub allowed_ips: Vec<String>,
+    // Прокси, которым разрешено передавать адрес клиента в X-Forwarded-For
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+}
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Performance { pub max_cpu_usage: u8, pub max_memory_usage: u8, pub cache_enabled: bool, pub cache_size: u32, pub compression_enabled: bool, pub rate_limit_enabled: bool, pub max_requests_per_minute: u32 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Notifications { pub email_notifications: bool, pub system_alerts: bool, pub user_registration: bool, pub error_reports: bool, pub backup_reports: bool, pub security_events: bool }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Api { pub enabled: bool, pub version: String, pub rate_limit: u32, pub require_auth: bool, pub allow_cors: bool, pub log_requests: bool }
+// Хранение журнала: окно последних записей в памяти, остальное — в сегментах на диске.
+// Сроки и размер: 0 — без ограничения; срок категории переопределяет общий.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogsConfig {
+    pub memory_entries: usize,
+    pub segment_size_mb: u32,
+    pub max_age_days: u32,
+    pub max_total_size_mb: u32,
+    #[serde(default)]
+    pub category_max_age_days: HashMap<LogCategory, u32>,
+    // С какого уровня собственные события сервера попадают в журнал
+    #[serde(default)]
+    pub capture_level: CaptureLevel,
+    // Локальные файлы журналов, за которыми сервер следит
+    #[serde(default)]
+    pub files: Vec<TailSource>,
+    // Конвейер разбора для сообщений, принятых по syslog
+    #[serde(default)]
+    pub syslog_pipeline: Option<String>,
+    // Такие же записи (уровень, категория, сообщение), пришедшие за это время
+    // после первой, схлопываются в неё со счётчиком; 0 — выключено
+    #[serde(default)]
+    pub dedup_window_secs: u64,
+    // Из записей Debug и Info этих категорий сохраняется только каждая N-я
+    #[serde(default)]
+    pub sample_every: HashMap<LogCategory, u32>,
+}
+
+impl Default for LogsConfig {
+    fn default() -> Self {
+        Self { memory_entries: 10_000, segment_size_mb: 16, max_age_days: 30, max_total_size_mb: 1024, category_max_age_days: HashMap::new(), capture_level: CaptureLevel::default(), files: Vec::new(), syslog_pipeline: None, dedup_window_secs: 0, sample_every: HashMap::new() }
+    }
+}
+
+// Дольше держать окно повторов нет смысла: счётчик на диске обновляется только при его закрытии
+const MAX_DEDUP_WINDOW_SECS: u64 = 3600;
 
-#[derive(Debug, Default, Clone)]
-pub struct SettingsStore { pub inner: Arc<RwLock<ServerConfig>> }
+impl LogsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.memory_entries == 0 { return Err("logs.memory_entries must be positive".into()); }
+        if self.segment_size_mb == 0 { return Err("logs.segment_size_mb must be positive".into()); }
+        if self.dedup_window_secs > MAX_DEDUP_WINDOW_SECS { return Err(format!("logs.dedup_window_secs must not exceed {}", MAX_DEDUP_WINDOW_SECS)); }
+        if self.sample_every.values().any(|n| *n == 0) { return Err("logs.sample_every values must be positive".into()); }
+        logs::validate_tail_sources(&self.files).map_err(|e| format!("logs.files: {}", e))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SettingsStore { pub inner: Arc<RwLock<ServerConfig>>, db: Collection, changes: Arc<watch::Sender<ServerConfig>> }
+
+const CONFIG_ID: &str = "server";
 
 impl SettingsStore {
-    pub fn new_default() -> Self {
-        let cfg = ServerConfig {
-            general: General { server_name: "Мой Проект".into(), description: "Описание проекта".into(), admin_email: "admin@example.com".into(), timezone: "Europe/Moscow".into(), language: "ru".into(), maintenance_mode: false },
+    pub fn open(db: &Db) -> Self {
+        let db = Collection::new(db, "settings");
+        let cfg = db.load_or_seed(|_: &ServerConfig| CONFIG_ID.into(), || vec![Self::defaults()])
+            .pop()
+            .unwrap_or_else(Self::defaults);
+        let changes = Arc::new(watch::Sender::new(cfg.clone()));
+        Self { inner: Arc::new(RwLock::new(cfg)), db, changes }
+    }
+
+    // Подписка на изменения настроек — для фоновых задач, которым нужна реакция на сохранение
+    pub fn subscribe(&self) -> watch::Receiver<ServerConfig> { self.changes.subscribe() }
+
+    pub async fn update<R>(&self, f: impl FnOnce(&mut ServerConfig) -> R) -> R {
+        let mut current = self.inner.write().await;
+        let result = f(&mut current);
+        self.db.put(CONFIG_ID, &*current);
+        self.changes.send_replace(current.clone());
+        result
+    }
+
+    fn defaults() -> ServerConfig {
+        ServerConfig {
+            general: General { server_name: "Мой Проект".into(), description: "Описание проекта".into(), admin_email: "admin@example.com".into(), timezone: "Europe/Moscow".into(), language: "ru".into(), maintenance_mode: false, maintenance_message: None, maintenance_until: None, maintenance_announce: false },
             connection: Connection { host: "localhost".into(), port: 3000, protocol: "https".into(), ssl_enabled: true, api_endpoint: "/api/v1".into(), connection_timeout: 30, retry_attempts: 3, keep_alive: true },
             database: Database { host: "localhost".into(), port: 5432, name: "project_db".into(), max_connections: 100, timeout: 30, auto_backup: true, backup_interval: 24 },
-            security: Security { enable_ssl: true, require_two_factor: false, session_timeout: 24, max_login_attempts: 5, ip_whitelist: false, allowed_ips: vec![] },
+            security: Security { enable_ssl: true, require_two_factor: false, session_timeout: 24, max_login_attempts: 5, ip_whitelist: false, allowed_ips: vec![], trusted_proxies: vec![] },
             performance: Performance { max_cpu_usage: 80, max_memory_usage: 85, cache_enabled: true, cache_size: 256, compression_enabled: true, rate_limit_enabled: true, max_requests_per_minute: 1000 },
             notifications: Notifications { email_notifications: true, system_alerts: true, user_registration: true, error_reports: true, backup_reports: true, security_events: true },
             api: Api { enabled: true, version: "v2.0".into(), rate_limit: 100, require_auth: true, allow_cors: false, log_requests: true },
-        };
-        Self { inner: Arc::new(RwLock::new(cfg)) }
+            logs: LogsConfig::default(),
+        }
     }
 }
 
@@ -51,9 +148,16 @@ pub async fn get_settings(State(store): State<SettingsStore>) -> Json<ServerConf
     Json(store.inner.read().await.clone())
 }
 
-pub async fn update_settings(State(store): State<SettingsStore>, Json(cfg): Json<ServerConfig>) -> impl IntoResponse {
-    *store.inner.write().await = cfg.clone();
-    (StatusCode::OK, Json(cfg))
+pub async fn update_settings(State(store): State<SettingsStore>, ClientIp(ip): ClientIp, Json(cfg): Json<ServerConfig>) -> impl IntoResponse {
+    if let Err(e) = ip_filter::validate(&cfg.security).and_then(|_| cfg.logs.validate()) {
+        return (StatusCode::BAD_REQUEST, e).into_response();
+    }
+    // Не даём администратору отрезать самого себя новым списком адресов
+    if !ip_filter::is_allowed(&cfg.security, ip) {
+        return (StatusCode::BAD_REQUEST, format!("allowed_ips does not include your address {}", ip)).into_response();
+    }
+    store.update(|current| *current = cfg.clone()).await;
+    (StatusCode::OK, Json(cfg)).into_response()
 }
 
 
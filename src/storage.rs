@@ -0,0 +1,212 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+
+// Хранилище записей: коллекция -> упорядоченный набор (id, json)
+pub trait Storage: Send + Sync {
+    // None — коллекция ещё ни разу не сохранялась (первый запуск)
+    fn load(&self, collection: &str) -> Result<Option<Vec<String>>, String>;
+    fn upsert(&self, collection: &str, id: &str, body: &str) -> Result<(), String>;
+    fn remove(&self, collection: &str, id: &str) -> Result<(), String>;
+    fn replace(&self, collection: &str, items: &[(String, String)]) -> Result<(), String>;
+}
+
+// Данные живут только в памяти процесса — для тестов и DC_STORAGE=memory
+#[derive(Default)]
+pub struct MemoryStorage { data: Mutex<HashMap<String, Vec<(String, String)>>> }
+
+impl Storage for MemoryStorage {
+    fn load(&self, collection: &str) -> Result<Option<Vec<String>>, String> {
+        let data = self.data.lock().unwrap();
+        Ok(data.get(collection).map(|rows| rows.iter().map(|(_, body)| body.clone()).collect()))
+    }
+
+    fn upsert(&self, collection: &str, id: &str, body: &str) -> Result<(), String> {
+        let mut data = self.data.lock().unwrap();
+        let rows = data.entry(collection.to_string()).or_default();
+        match rows.iter_mut().find(|(k, _)| k == id) {
+            Some(row) => row.1 = body.to_string(),
+            None => rows.push((id.to_string(), body.to_string())),
+        }
+        Ok(())
+    }
+
+    fn remove(&self, collection: &str, id: &str) -> Result<(), String> {
+        if let Some(rows) = self.data.lock().unwrap().get_mut(collection) { rows.retain(|(k, _)| k != id); }
+        Ok(())
+    }
+
+    fn replace(&self, collection: &str, items: &[(String, String)]) -> Result<(), String> {
+        self.data.lock().unwrap().insert(collection.to_string(), items.to_vec());
+        Ok(())
+    }
+}
+
+// Встроенная SQLite: одна таблица записей, порядок — по seq
+pub struct SqliteStorage { conn: Mutex<Connection> }
+
+impl SqliteStorage {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| e.to_string())?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS collections (name TEXT PRIMARY KEY);
+             CREATE TABLE IF NOT EXISTS records (
+                 seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                 collection TEXT NOT NULL,
+                 id TEXT NOT NULL,
+                 body TEXT NOT NULL,
+                 UNIQUE (collection, id)
+             );",
+        ).map_err(|e| e.to_string())?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load(&self, collection: &str) -> Result<Option<Vec<String>>, String> {
+        let conn = self.conn.lock().unwrap();
+        let known = conn.query_row("SELECT 1 FROM collections WHERE name = ?1", params![collection], |_| Ok(()))
+            .optional().map_err(|e| e.to_string())?;
+        if known.is_none() { return Ok(None); }
+        let mut stmt = conn.prepare("SELECT body FROM records WHERE collection = ?1 ORDER BY seq").map_err(|e| e.to_string())?;
+        let rows = stmt.query_map(params![collection], |r| r.get::<_, String>(0)).map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<_>, _>>().map(Some).map_err(|e| e.to_string())
+    }
+
+    fn upsert(&self, collection: &str, id: &str, body: &str) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("INSERT OR IGNORE INTO collections (name) VALUES (?1)", params![collection]).map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO records (collection, id, body) VALUES (?1, ?2, ?3)
+             ON CONFLICT (collection, id) DO UPDATE SET body = excluded.body",
+            params![collection, id, body],
+        ).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn remove(&self, collection: &str, id: &str) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM records WHERE collection = ?1 AND id = ?2", params![collection, id]).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn replace(&self, collection: &str, items: &[(String, String)]) -> Result<(), String> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        tx.execute("INSERT OR IGNORE INTO collections (name) VALUES (?1)", params![collection]).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM records WHERE collection = ?1", params![collection]).map_err(|e| e.to_string())?;
+        for (id, body) in items {
+            tx.execute("INSERT INTO records (collection, id, body) VALUES (?1, ?2, ?3)", params![collection, id, body]).map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())
+    }
+}
+
+pub type Db = Arc<dyn Storage>;
+
+// Выбор бэкенда при старте: DC_STORAGE=memory|sqlite, путь к базе — DC_DB_PATH
+pub fn open_from_env() -> Db {
+    match std::env::var("DC_STORAGE").as_deref() {
+        Ok("memory") => {
+            tracing::warn!("DC_STORAGE=memory: data will not survive a restart");
+            Arc::new(MemoryStorage::default())
+        }
+        _ => {
+            let path = std::env::var("DC_DB_PATH").unwrap_or_else(|_| "deploy_center.db".into());
+            tracing::info!(%path, "Using SQLite storage");
+            Arc::new(SqliteStorage::open(&path).expect("failed to open SQLite storage"))
+        }
+    }
+}
+
+// Типизированная обёртка над одной коллекцией. Ошибки записи логируются:
+// состояние в памяти остаётся источником истины для текущего процесса.
+#[derive(Clone)]
+pub struct Collection { db: Db, name: &'static str, newest_first: bool }
+
+impl std::fmt::Debug for Collection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Collection").field("name", &self.name).finish()
+    }
+}
+
+impl Collection {
+    pub fn new(db: &Db, name: &'static str) -> Self { Self { db: db.clone(), name, newest_first: false } }
+
+    // Для сторов, которые вставляют новые элементы в начало Vec
+    pub fn newest_first(mut self) -> Self { self.newest_first = true; self }
+
+    pub fn load_or_seed<T: Serialize + DeserializeOwned>(&self, key: impl Fn(&T) -> String, seed: impl FnOnce() -> Vec<T>) -> Vec<T> {
+        match self.db.load(self.name) {
+            Ok(Some(rows)) => {
+                let mut items: Vec<T> = rows.iter().filter_map(|body| match serde_json::from_str(body) {
+                    Ok(item) => Some(item),
+                    Err(e) => { tracing::error!(collection = self.name, error = %e, "Skipping unreadable record"); None }
+                }).collect();
+                if self.newest_first { items.reverse(); }
+                items
+            }
+            Ok(None) => { let items = seed(); self.replace(&items, key); items }
+            Err(e) => panic!("failed to load collection {}: {}", self.name, e),
+        }
+    }
+
+    pub fn put<T: Serialize>(&self, id: &str, item: &T) {
+        let body = serde_json::to_string(item).expect("serializable record");
+        if let Err(e) = self.db.upsert(self.name, id, &body) {
+            tracing::error!(collection = self.name, %id, error = %e, "Failed to persist record");
+        }
+    }
+
+    pub fn remove(&self, id: &str) {
+        if let Err(e) = self.db.remove(self.name, id) {
+            tracing::error!(collection = self.name, %id, error = %e, "Failed to remove record");
+        }
+    }
+
+    pub fn replace<T: Serialize>(&self, items: &[T], key: impl Fn(&T) -> String) {
+        let mut rows: Vec<(String, String)> = items.iter()
+            .map(|item| (key(item), serde_json::to_string(item).expect("serializable record")))
+            .collect();
+        if self.newest_first { rows.reverse(); }
+        if let Err(e) = self.db.replace(self.name, &rows) {
+            tracing::error!(collection = self.name, error = %e, "Failed to persist collection");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Item { id: String, value: u32 }
+
+    fn item(id: &str, value: u32) -> Item { Item { id: id.into(), value } }
+
+    #[test]
+    fn sqlite_collection_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("dc_storage_{}.db", uuid::Uuid::new_v4()));
+        let open = || -> Db { Arc::new(SqliteStorage::open(path.to_str().unwrap()).unwrap()) };
+        let key = |i: &Item| i.id.clone();
+        {
+            let items = Collection::new(&open(), "items");
+            // Первый запуск: коллекция заполняется затравкой и сохраняется
+            assert_eq!(items.load_or_seed(key, || vec![item("a", 1), item("b", 2)]), [item("a", 1), item("b", 2)]);
+            items.put("b", &item("b", 20));
+            items.put("c", &item("c", 3));
+            items.remove("a");
+        }
+        let items = Collection::new(&open(), "items");
+        // Затравка не используется, порядок — порядок вставки
+        assert_eq!(items.load_or_seed(key, || vec![item("z", 0)]), [item("b", 20), item("c", 3)]);
+        items.replace(&[item("d", 4)], key);
+        assert_eq!(Collection::new(&open(), "items").load_or_seed(key, Vec::new), [item("d", 4)]);
+        // Пустая, но уже сохранённая коллекция не заполняется затравкой заново
+        items.replace::<Item>(&[], key);
+        assert!(Collection::new(&open(), "items").load_or_seed(key, || vec![item("z", 0)]).is_empty());
+        for suffix in ["", "-wal", "-shm"] { let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix)); }
+    }
+}
@@ -2,19 +2,27 @@ use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use uuid::Uuid;
+use crate::storage::{Collection, Db};
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TerminalLine { pub id: String, pub kind: String, pub content: String, pub timestamp: i64 }
 
-#[derive(Debug, Default, Clone)]
-pub struct TerminalStore { pub lines: Arc<RwLock<Vec<TerminalLine>>> }
+#[derive(Debug, Clone)]
+pub struct TerminalStore { pub lines: Arc<RwLock<Vec<TerminalLine>>>, db: Collection }
 
 impl TerminalStore {
-    pub fn new_welcome() -> Self {
-        let mut v = Vec::new();
-        v.push(TerminalLine { id: "1".into(), kind: "output".into(), content: "Добро пожаловать в терминал панели управления v2.1.0".into(), timestamp: chrono::Utc::now().timestamp_millis() });
-        v.push(TerminalLine { id: "2".into(), kind: "output".into(), content: "Введите \"help\" для получения списка доступных команд.".into(), timestamp: chrono::Utc::now().timestamp_millis() });
-        Self { lines: Arc::new(RwLock::new(v)) }
+    pub fn open(db: &Db) -> Self {
+        let db = Collection::new(db, "terminal");
+        let lines = db.load_or_seed(|l: &TerminalLine| l.id.clone(), Self::welcome);
+        Self { lines: Arc::new(RwLock::new(lines)), db }
+    }
+
+    fn welcome() -> Vec<TerminalLine> {
+        vec![
+            TerminalLine { id: "1".into(), kind: "output".into(), content: "Добро пожаловать в терминал панели управления v2.1.0".into(), timestamp: chrono::Utc::now().timestamp_millis() },
+            TerminalLine { id: "2".into(), kind: "output".into(), content: "Введите \"help\" для получения списка доступных команд.".into(), timestamp: chrono::Utc::now().timestamp_millis() },
+        ]
     }
 }
 
@@ -28,18 +36,21 @@ pub async fn get_history(State(store): State<TerminalStore>) -> Json<Vec<Termina
 pub async fn exec_command(State(store): State<TerminalStore>, Json(req): Json<ExecRequest>) -> impl IntoResponse {
     let mut lines = store.lines.write().await;
     let now = chrono::Utc::now().timestamp_millis();
-    let id_cmd = format!("{}", now);
-    lines.push(TerminalLine { id: id_cmd.clone(), kind: "command".into(), content: format!("$ {}", req.command), timestamp: now });
+    // id по времени совпадали у команды и её вывода — в хранилище это один ключ
+    let cmd = TerminalLine { id: Uuid::new_v4().to_string(), kind: "command".into(), content: format!("$ {}", req.command), timestamp: now };
+    store.db.put(&cmd.id, &cmd);
+    lines.push(cmd);
 
     let (output, kind) = match req.command.trim() {
         "pwd" => ("/home/user/project".into(), "output".into()),
         "whoami" => ("admin".into(), "output".into()),
         "help" => ("Доступные команды: ls, pwd, whoami, date, clear, help".into(), "output".into()),
-        "clear" => { lines.clear(); return StatusCode::NO_CONTENT.into_response(); }
+        "clear" => { lines.clear(); store.db.replace::<TerminalLine>(&[], |l| l.id.clone()); return StatusCode::NO_CONTENT.into_response(); }
         other => (format!("Команда не найдена: {}", other), "error".into()),
     };
-    let id_out = format!("{}", chrono::Utc::now().timestamp_millis());
-    lines.push(TerminalLine { id: id_out, kind, content: output, timestamp: chrono::Utc::now().timestamp_millis() });
+    let out = TerminalLine { id: Uuid::new_v4().to_string(), kind, content: output, timestamp: chrono::Utc::now().timestamp_millis() };
+    store.db.put(&out.id, &out);
+    lines.push(out);
     StatusCode::OK.into_response()
 }
 
@@ -0,0 +1,185 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use crate::auth::{AuthState, CurrentUser};
+
+// RFC 6238: HMAC-SHA1, шаг 30 секунд, 6 цифр
+const STEP_SECS: i64 = 30;
+const DIGITS: u32 = 6;
+const RECOVERY_CODES: usize = 10;
+// 80 бит: 16 символов base32 группами по четыре
+const RECOVERY_CODE_BYTES: usize = 10;
+
+pub fn generate_secret() -> String {
+    let mut key = [0u8; 20];
+    OsRng.fill_bytes(&mut key);
+    BASE32_NOPAD.encode(&key)
+}
+
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("hmac accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let bin = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    bin % 10u32.pow(DIGITS)
+}
+
+// Проверяет код с допуском ±1 шаг. Возвращает принятый шаг; шаги не новее
+// `last_step` отклоняются, чтобы один и тот же код нельзя было использовать дважды.
+pub fn verify(secret: &str, code: &str, last_step: Option<u64>) -> Option<u64> {
+    verify_at(secret, code, last_step, Utc::now())
+}
+
+fn verify_at(secret: &str, code: &str, last_step: Option<u64>, now: DateTime<Utc>) -> Option<u64> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code = code.trim();
+    if code.len() != DIGITS as usize { return None; }
+    let code: u32 = code.parse().ok()?;
+    let current = (now.timestamp() / STEP_SECS) as u64;
+    (current.saturating_sub(1)..=current + 1)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| hotp(&key, *step) == code)
+}
+
+fn url_encode(s: &str) -> String {
+    s.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+pub fn provisioning_uri(issuer: &str, username: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        url_encode(issuer), url_encode(username), secret, url_encode(issuer), DIGITS, STEP_SECS
+    )
+}
+
+// Регистр, дефисы и пробелы при вводе кода не важны
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars().filter(char::is_ascii_alphanumeric).map(|c| c.to_ascii_lowercase()).collect()
+}
+
+// Коды восстановления хешируются так же, как пароли
+pub fn hash_recovery_code(code: &str) -> String {
+    crate::auth::hash_password(&normalize_recovery_code(code))
+}
+
+// Сохранённый хеш, которому соответствует введённый код
+pub fn find_recovery_code<'a>(hashes: &'a [String], code: &str) -> Option<&'a String> {
+    let normalized = normalize_recovery_code(code);
+    hashes.iter().find(|hash| crate::auth::verify_password(&normalized, hash))
+}
+
+fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES).map(|_| {
+        let mut raw = [0u8; RECOVERY_CODE_BYTES];
+        OsRng.fill_bytes(&mut raw);
+        let encoded = BASE32_NOPAD.encode(&raw).to_lowercase();
+        encoded.as_bytes().chunks(4).map(|c| String::from_utf8_lossy(c)).collect::<Vec<_>>().join("-")
+    }).collect()
+}
+
+#[derive(Debug, Serialize)]
+pub struct Enrollment { pub secret: String, pub provisioning_uri: String }
+
+// Начинает подключение 2FA: секрет остаётся "ожидающим" до подтверждения кодом
+pub async fn enroll(State(state): State<AuthState>, CurrentUser(user): CurrentUser) -> impl IntoResponse {
+    if user.credentials.totp_secret.is_some() {
+        return (StatusCode::CONFLICT, "Two-factor authentication is already enabled").into_response();
+    }
+    let secret = generate_secret();
+    let issuer = state.settings.inner.read().await.general.server_name.clone();
+    let pending = secret.clone();
+    state.users.update(user.id, move |u| u.credentials.totp_pending = Some(pending)).await;
+    let provisioning_uri = provisioning_uri(&issuer, &user.username, &secret);
+    Json(Enrollment { secret, provisioning_uri }).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmRequest { pub code: String }
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodes { pub recovery_codes: Vec<String> }
+
+// Подтверждает подключение; коды восстановления показываются только один раз
+pub async fn confirm(State(state): State<AuthState>, CurrentUser(user): CurrentUser, Json(req): Json<ConfirmRequest>) -> impl IntoResponse {
+    let Some(pending) = user.credentials.totp_pending.clone() else {
+        return (StatusCode::BAD_REQUEST, "No pending two-factor enrollment").into_response();
+    };
+    let Some(step) = verify(&pending, &req.code, None) else {
+        return (StatusCode::UNAUTHORIZED, "Invalid two-factor code").into_response();
+    };
+    let codes = generate_recovery_codes();
+    let hashed: Vec<String> = codes.iter().map(|c| hash_recovery_code(c)).collect();
+    state.users.update(user.id, move |u| {
+        u.credentials.totp_secret = u.credentials.totp_pending.take();
+        u.credentials.totp_last_step = Some(step);
+        u.credentials.recovery_codes = hashed;
+    }).await;
+    Json(RecoveryCodes { recovery_codes: codes }).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238, приложение B (SHA1): ключ "12345678901234567890", коды из 8 цифр усечены до 6
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+    const RFC_VECTORS: &[(i64, u32)] = &[
+        (59, 287082),
+        (1111111109, 81804),
+        (1111111111, 50471),
+        (1234567890, 5924),
+        (2000000000, 279037),
+        (20000000000, 353130),
+    ];
+
+    fn at(secs: i64) -> DateTime<Utc> { DateTime::from_timestamp(secs, 0).unwrap() }
+
+    #[test]
+    fn matches_rfc_6238_vectors() {
+        for &(time, code) in RFC_VECTORS {
+            assert_eq!(hotp(RFC_SECRET, (time / STEP_SECS) as u64), code, "T = {}", time);
+        }
+    }
+
+    #[test]
+    fn verify_accepts_adjacent_steps_once() {
+        let secret = BASE32_NOPAD.encode(RFC_SECRET);
+        let step = (1111111109 / STEP_SECS) as u64;
+        assert_eq!(verify_at(&secret, "081804", None, at(1111111109)), Some(step));
+        // Соседний шаг допускается в обе стороны, дальше — нет
+        assert_eq!(verify_at(&secret, " 081804 ", None, at(1111111109 + STEP_SECS)), Some(step));
+        assert_eq!(verify_at(&secret, "081804", None, at(1111111109 + 2 * STEP_SECS)), None);
+        // Повтор уже принятого кода отклоняется
+        assert_eq!(verify_at(&secret, "081804", Some(step), at(1111111109)), None);
+        assert_eq!(verify_at(&secret, "81804", None, at(1111111109)), None);
+        assert_eq!(verify_at("not base32!", "081804", None, at(1111111109)), None);
+    }
+
+    #[test]
+    fn recovery_codes_carry_80_bits() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODES);
+        for code in &codes {
+            let raw = BASE32_NOPAD.decode(normalize_recovery_code(code).to_uppercase().as_bytes()).unwrap();
+            assert_eq!(raw.len() * 8, 80);
+        }
+        assert_eq!(codes.iter().collect::<std::collections::HashSet<_>>().len(), RECOVERY_CODES);
+    }
+
+    #[test]
+    fn finds_recovery_code_by_argon2_hash() {
+        let hashes = vec![hash_recovery_code("abcd-efgh-ijkl-mnop"), hash_recovery_code("0a1b-2c3d")];
+        assert!(hashes.iter().all(|h| h.starts_with("$argon2")));
+        assert_eq!(find_recovery_code(&hashes, " ABCD efgh-ijkl-MNOP "), Some(&hashes[0]));
+        assert_eq!(find_recovery_code(&hashes, "0A1B-2C3D"), Some(&hashes[1]));
+        assert_eq!(find_recovery_code(&hashes, "abcd-efgh-ijkl-mnoq"), None);
+    }
+}
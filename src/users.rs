@@ -4,6 +4,7 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 use uuid::Uuid;
+use crate::{auth::AuthState, storage::{Collection, Db}};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -26,6 +27,45 @@ pub struct User {
     pub posts: u32,
     pub reputation: i32,
     pub permissions: Vec<String>,
+    #[serde(skip)]
+    pub credentials: Credentials,
+}
+
+// Секреты пользователя: лежат в хранилище, но никогда не отдаются через API
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Credentials {
+    pub password_hash: Option<String>,
+    // Подтверждённый секрет TOTP (base32)
+    #[serde(default)]
+    pub totp_secret: Option<String>,
+    // Секрет, выданный при подключении, но ещё не подтверждённый кодом
+    #[serde(default)]
+    pub totp_pending: Option<String>,
+    // Последний принятый шаг TOTP — защита от повторного использования кода
+    #[serde(default)]
+    pub totp_last_step: Option<u64>,
+    // argon2-хеши неиспользованных кодов восстановления (у старых — sha256)
+    #[serde(default)]
+    pub recovery_codes: Vec<String>,
+    // Неудачные входы подряд и временная блокировка, когда их стало больше Security.max_login_attempts
+    #[serde(default)]
+    pub failed_logins: u32,
+    #[serde(default)]
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+// Запись в хранилище — пользователь вместе с секретами
+#[derive(Serialize, Deserialize)]
+struct UserRecord {
+    #[serde(flatten)]
+    user: User,
+    #[serde(default)]
+    credentials: Credentials,
+}
+
+impl UserRecord {
+    fn from_user(u: &User) -> Self { Self { user: u.clone(), credentials: u.credentials.clone() } }
+    fn into_user(self) -> User { User { credentials: self.credentials, ..self.user } }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -36,19 +76,67 @@ pub struct UpsertUser {
     pub role: UserRole,
     pub status: UserStatus,
     pub permissions: Vec<String>,
+    #[serde(default)]
+    pub password: Option<String>,
 }
 
-#[derive(Debug, Default, Clone)]
-pub struct UsersStore { inner: Arc<RwLock<Vec<User>>> }
+#[derive(Debug, Clone)]
+pub struct UsersStore { inner: Arc<RwLock<Vec<User>>>, db: Collection }
 
 impl UsersStore {
-    pub fn new_with_mock() -> Self {
+    pub fn open(db: &Db) -> Self {
+        let db = Collection::new(db, "users").newest_first();
+        let seed = || Self::mock().iter().map(UserRecord::from_user).collect();
+        let mut users: Vec<User> = db.load_or_seed(|r: &UserRecord| r.user.id.to_string(), seed)
+            .into_iter()
+            .map(UserRecord::into_user)
+            .collect();
+        Self::bootstrap_admin(&db, &mut users);
+        Self { inner: Arc::new(RwLock::new(users)), db }
+    }
+
+    // Если войти не может никто, первому администратору выдаётся пароль
+    // из DC_ADMIN_PASSWORD (или случайный, который печатается в лог)
+    fn bootstrap_admin(db: &Collection, users: &mut [User]) {
+        if users.iter().any(|u| u.credentials.password_hash.is_some()) { return; }
+        let Some(admin) = users.iter_mut().find(|u| u.role == UserRole::Admin) else { return };
+        let password = match std::env::var("DC_ADMIN_PASSWORD") {
+            Ok(p) if !p.is_empty() => p,
+            _ => {
+                let p = crate::auth::random_token(12);
+                tracing::warn!(username = %admin.username, password = %p, "Generated initial admin password");
+                p
+            }
+        };
+        admin.credentials.password_hash = Some(crate::auth::hash_password(&password));
+        db.put(&admin.id.to_string(), &UserRecord::from_user(admin));
+    }
+
+    fn persist(&self, u: &User) { self.db.put(&u.id.to_string(), &UserRecord::from_user(u)); }
+
+    pub async fn get(&self, id: Uuid) -> Option<User> {
+        self.inner.read().await.iter().find(|u| u.id == id).cloned()
+    }
+
+    // Изменяет пользователя и сохраняет его; None — пользователя нет
+    pub async fn update<R>(&self, id: Uuid, f: impl FnOnce(&mut User) -> R) -> Option<R> {
+        let mut data = self.inner.write().await;
+        let u = data.iter_mut().find(|u| u.id == id)?;
+        let result = f(u);
+        self.persist(u);
+        Some(result)
+    }
+
+    pub async fn find_by_username(&self, username: &str) -> Option<User> {
+        self.inner.read().await.iter().find(|u| u.username == username).cloned()
+    }
+
+    fn mock() -> Vec<User> {
         let now = Utc::now();
-        let users = vec![
-            User { id: Uuid::new_v4(), username: "admin".into(), email: "admin@example.com".into(), full_name: "Главный администратор".into(), role: UserRole::Admin, status: UserStatus::Active, join_date: now, last_activity: "2 минуты назад".into(), posts: 156, reputation: 9850, permissions: vec!["all".into()] },
-            User { id: Uuid::new_v4(), username: "moderator1".into(), email: "mod1@example.com".into(), full_name: "Модератор Иван".into(), role: UserRole::Moderator, status: UserStatus::Active, join_date: now, last_activity: "1 час назад".into(), posts: 89, reputation: 4520, permissions: vec!["moderate".into(), "edit".into(), "delete".into()] },
-        ];
-        Self { inner: Arc::new(RwLock::new(users)) }
+        vec![
+            User { id: Uuid::new_v4(), username: "admin".into(), email: "admin@example.com".into(), full_name: "Главный администратор".into(), role: UserRole::Admin, status: UserStatus::Active, join_date: now, last_activity: "2 минуты назад".into(), posts: 156, reputation: 9850, permissions: vec!["all".into()], credentials: Credentials::default() },
+            User { id: Uuid::new_v4(), username: "moderator1".into(), email: "mod1@example.com".into(), full_name: "Модератор Иван".into(), role: UserRole::Moderator, status: UserStatus::Active, join_date: now, last_activity: "1 час назад".into(), posts: 89, reputation: 4520, permissions: vec!["moderate".into(), "edit".into(), "delete".into()], credentials: Credentials::default() },
+        ]
     }
 }
 
@@ -58,6 +146,9 @@ pub async fn list_users(State(store): State<UsersStore>) -> impl IntoResponse {
 
 pub async fn create_user(State(store): State<UsersStore>, Json(payload): Json<UpsertUser>) -> impl IntoResponse {
     let mut data = store.inner.write().await;
+    if data.iter().any(|u| u.username == payload.username) {
+        return (StatusCode::CONFLICT, "Username already taken").into_response();
+    }
     let user = User {
         id: Uuid::new_v4(),
         username: payload.username.clone(),
@@ -70,13 +161,19 @@ pub async fn create_user(State(store): State<UsersStore>, Json(payload): Json<Up
         posts: 0,
         reputation: 0,
         permissions: payload.permissions,
+        credentials: Credentials { password_hash: payload.password.as_deref().map(crate::auth::hash_password), ..Credentials::default() },
     };
     data.insert(0, user.clone());
-    (StatusCode::CREATED, Json(user))
+    store.persist(&user);
+    (StatusCode::CREATED, Json(user)).into_response()
 }
 
 pub async fn update_user(State(store): State<UsersStore>, Path(id): Path<Uuid>, Json(payload): Json<UpsertUser>) -> impl IntoResponse {
     let mut data = store.inner.write().await;
+    // Иначе вход по имени стал бы неоднозначным
+    if data.iter().any(|u| u.id != id && u.username == payload.username) {
+        return (StatusCode::CONFLICT, "Username already taken").into_response();
+    }
     if let Some(u) = data.iter_mut().find(|u| u.id == id) {
         u.username = payload.username;
         u.email = payload.email;
@@ -84,6 +181,10 @@ pub async fn update_user(State(store): State<UsersStore>, Path(id): Path<Uuid>,
         u.role = payload.role;
         u.status = payload.status;
         u.permissions = payload.permissions;
+        if let Some(password) = payload.password.as_deref() {
+            u.credentials.password_hash = Some(crate::auth::hash_password(password));
+        }
+        store.persist(u);
         return Json(u.clone()).into_response();
     }
     (StatusCode::NOT_FOUND, "Not found").into_response()
@@ -93,9 +194,66 @@ pub async fn delete_user(State(store): State<UsersStore>, Path(id): Path<Uuid>)
     let mut data = store.inner.write().await;
     let before = data.len();
     data.retain(|u| u.id != id);
-    if data.len() != before { return StatusCode::NO_CONTENT.into_response(); }
+    if data.len() != before { store.db.remove(&id.to_string()); return StatusCode::NO_CONTENT.into_response(); }
     (StatusCode::NOT_FOUND, "Not found").into_response()
 }
 
+// Сброс 2FA администратором, например при потере устройства
+pub async fn reset_two_factor(State(store): State<UsersStore>, Path(id): Path<Uuid>) -> impl IntoResponse {
+    let reset = store.update(id, |u| {
+        u.credentials.totp_secret = None;
+        u.credentials.totp_pending = None;
+        u.credentials.totp_last_step = None;
+        u.credentials.recovery_codes.clear();
+    }).await;
+    match reset {
+        Some(()) => StatusCode::NO_CONTENT.into_response(),
+        None => (StatusCode::NOT_FOUND, "Not found").into_response(),
+    }
+}
+
+// Снятие блокировки после неудачных попыток входа — и учётной записи, и адресов, с которых под ней входили
+pub async fn unlock_user(State(state): State<AuthState>, Path(id): Path<Uuid>) -> impl IntoResponse {
+    let unlocked = state.users.update(id, |u| {
+        u.credentials.failed_logins = 0;
+        u.credentials.locked_until = None;
+        u.username.clone()
+    }).await;
+    match unlocked {
+        Some(username) => {
+            let max_attempts = state.settings.inner.read().await.security.max_login_attempts;
+            state.guard.clear_user(&username, max_attempts).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        None => (StatusCode::NOT_FOUND, "Not found").into_response(),
+    }
+}
 
+#[cfg(test)]
+mod tests {
+    use crate::storage::{Db, MemoryStorage};
+    use super::*;
 
+    fn rename(user: &User, username: &str) -> Json<UpsertUser> {
+        Json(UpsertUser {
+            username: username.into(), email: user.email.clone(), full_name: Some(user.full_name.clone()),
+            role: user.role.clone(), status: user.status.clone(), permissions: user.permissions.clone(), password: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn rename_cannot_take_another_username() {
+        let db: Db = Arc::new(MemoryStorage::default());
+        let store = UsersStore::open(&db);
+        let moderator = store.find_by_username("moderator1").await.unwrap();
+        let taken = update_user(State(store.clone()), Path(moderator.id), rename(&moderator, "admin")).await.into_response();
+        assert_eq!(taken.status(), StatusCode::CONFLICT);
+        assert_eq!(store.get(moderator.id).await.unwrap().username, "moderator1");
+        // Своё имя оставить можно
+        let same = update_user(State(store.clone()), Path(moderator.id), rename(&moderator, "moderator1")).await.into_response();
+        assert_eq!(same.status(), StatusCode::OK);
+        let renamed = update_user(State(store.clone()), Path(moderator.id), rename(&moderator, "moderator2")).await.into_response();
+        assert_eq!(renamed.status(), StatusCode::OK);
+        assert_eq!(store.find_by_username("moderator2").await.map(|u| u.id), Some(moderator.id));
+    }
+}